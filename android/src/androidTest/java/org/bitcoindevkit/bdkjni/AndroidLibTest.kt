@@ -13,6 +13,8 @@ import org.junit.runner.RunWith
  */
 @RunWith(AndroidJUnit4::class)
 class AndroidLibTest : LibTest() {
+    override val scriptVerification = false
+
     override fun getDataDir(): String {
         val context = ApplicationProvider.getApplicationContext<Application>()
         val dirName = "bdk-test-${randomDirId()}"
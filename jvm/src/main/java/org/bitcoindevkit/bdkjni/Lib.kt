@@ -8,6 +8,7 @@ import com.fasterxml.jackson.module.kotlin.readValue
 
 class Lib {
     external fun call(pattern: String): String
+    external fun call_with_signer(pattern: String, signer: ExternalSigner): String
 
     data class JsonRpc(var method: String, var params: JsonNode)
 
@@ -16,6 +17,10 @@ class Lib {
 
 
 
+    // ByteArrays would be serialized as base64 strings, send the raw bytes instead
+    private fun psbtBytesNode(psbt: ByteArray): JsonNode =
+        mapper.valueToTree(psbt.map { it.toInt() and 0xff })
+
     companion object {
         @JvmStatic
         fun load() {
@@ -34,6 +39,10 @@ class Lib {
         return mapper.treeToValue(json, WalletPtr::class.java)
     }
 
+    /**
+     * Free [wallet] once the calls already using it return. The handle must not be used again,
+     * including by other threads, after this is called
+     */
     fun destructor(wallet: WalletPtr) {
         val node = JsonNodeFactory.instance.objectNode()
         node.set("wallet", mapper.valueToTree<JsonNode>(wallet))
@@ -128,7 +137,7 @@ class Lib {
         return mapper.readValue(resString, mapper.typeFactory.constructCollectionType(List::class.java, TransactionDetails::class.java))
     }
 
-    fun create_tx(wallet: WalletPtr, fee_rate: Float, addressees: List<Pair<String, String>>, send_all: Boolean?=false, utxos: List<String>?=null, unspendable: List<String>?=null, policy: Map<String, List<String>>?=null): CreateTxResponse {
+    fun create_tx(wallet: WalletPtr, fee_rate: Float, addressees: List<Pair<String, String>>, send_all: Boolean?=false, utxos: List<String>?=null, unspendable: List<String>?=null, policy: Map<String, List<String>>?=null, psbt_format: PsbtFormat?=null, psbt_version: Int?=null): CreateTxResponse {
         val node = JsonNodeFactory.instance.objectNode()
         node.set("wallet", mapper.valueToTree<JsonNode>(wallet))
         node.set("fee_rate", mapper.valueToTree<JsonNode>(fee_rate))
@@ -137,6 +146,8 @@ class Lib {
         node.set("utxos", mapper.valueToTree<JsonNode>(utxos))
         node.set("unspendable", mapper.valueToTree<JsonNode>(unspendable))
         node.set("policy", mapper.valueToTree<JsonNode>(policy))
+        node.set("psbt_format", mapper.valueToTree<JsonNode>(psbt_format))
+        node.put("psbt_version", psbt_version)
         val req = JsonRpc("create_tx", node)
         val reqString = mapper.writeValueAsString(req)
         val resString = call(reqString)
@@ -148,11 +159,29 @@ class Lib {
         return mapper.treeToValue(json, CreateTxResponse::class.java)
     }
 
-    fun sign(wallet: WalletPtr, psbt: String, assume_height: Int?=null): SignResponse {
+    /**
+     * Sign a PSBT with the wallet's keys. The options map to bdk's `SignOptions`, `sighash_type`
+     * (eg. `SIGHASH_ALL|SIGHASH_ANYONECANPAY`) is applied to every input that doesn't already
+     * specify one and needs [allow_all_sighashes] for anything but `SIGHASH_ALL`
+     */
+    fun sign(wallet: WalletPtr, psbt: String, assume_height: Int?=null, trust_witness_utxo: Boolean?=null, allow_all_sighashes: Boolean?=null, remove_partial_sigs: Boolean?=null, try_finalize: Boolean?=null, sighash_type: String?=null, psbt_format: PsbtFormat?=null, psbt_version: Int?=null): SignResponse =
+        sign(wallet, mapper.valueToTree<JsonNode>(psbt), assume_height, trust_witness_utxo, allow_all_sighashes, remove_partial_sigs, try_finalize, sighash_type, psbt_format, psbt_version)
+
+    fun sign(wallet: WalletPtr, psbt: ByteArray, assume_height: Int?=null, trust_witness_utxo: Boolean?=null, allow_all_sighashes: Boolean?=null, remove_partial_sigs: Boolean?=null, try_finalize: Boolean?=null, sighash_type: String?=null, psbt_format: PsbtFormat?=null, psbt_version: Int?=null): SignResponse =
+        sign(wallet, psbtBytesNode(psbt), assume_height, trust_witness_utxo, allow_all_sighashes, remove_partial_sigs, try_finalize, sighash_type, psbt_format, psbt_version)
+
+    private fun sign(wallet: WalletPtr, psbt: JsonNode, assume_height: Int?, trust_witness_utxo: Boolean?, allow_all_sighashes: Boolean?, remove_partial_sigs: Boolean?, try_finalize: Boolean?, sighash_type: String?, psbt_format: PsbtFormat?, psbt_version: Int?): SignResponse {
         val node = JsonNodeFactory.instance.objectNode()
         node.set("wallet", mapper.valueToTree<JsonNode>(wallet))
-        node.set("psbt", mapper.valueToTree<JsonNode>(psbt))
+        node.set("psbt", psbt)
+        node.set("psbt_format", mapper.valueToTree<JsonNode>(psbt_format))
+        node.put("psbt_version", psbt_version)
         node.set("assume_height", mapper.valueToTree<JsonNode>(assume_height))
+        node.set("trust_witness_utxo", mapper.valueToTree<JsonNode>(trust_witness_utxo))
+        node.set("allow_all_sighashes", mapper.valueToTree<JsonNode>(allow_all_sighashes))
+        node.set("remove_partial_sigs", mapper.valueToTree<JsonNode>(remove_partial_sigs))
+        node.set("try_finalize", mapper.valueToTree<JsonNode>(try_finalize))
+        node.set("sighash_type", mapper.valueToTree<JsonNode>(sighash_type))
         val req = JsonRpc("sign", node)
         val reqString = mapper.writeValueAsString(req)
         val resString = call(reqString)
@@ -164,10 +193,16 @@ class Lib {
         return mapper.treeToValue(json, SignResponse::class.java)
     }
 
-    fun extract_psbt(wallet: WalletPtr, psbt: String): RawTransaction {
+    fun extract_psbt(wallet: WalletPtr, psbt: String): RawTransaction =
+        extract_psbt(wallet, mapper.valueToTree<JsonNode>(psbt))
+
+    fun extract_psbt(wallet: WalletPtr, psbt: ByteArray): RawTransaction =
+        extract_psbt(wallet, psbtBytesNode(psbt))
+
+    private fun extract_psbt(wallet: WalletPtr, psbt: JsonNode): RawTransaction {
         val node = JsonNodeFactory.instance.objectNode()
         node.set("wallet", mapper.valueToTree<JsonNode>(wallet))
-        node.set("psbt", mapper.valueToTree<JsonNode>(psbt))
+        node.set("psbt", psbt)
         val req = JsonRpc("extract_psbt", node)
         val reqString = mapper.writeValueAsString(req)
         val resString = call(reqString)
@@ -208,11 +243,304 @@ class Lib {
         return mapper.treeToValue(json, PublicDescriptorsResponse::class.java)
     }
 
-    fun generate_extended_key(network: Network, mnemonicWordCount: Int, password: String?): ExtendedKey {
+    /**
+     * Build a signed transaction moving all the funds of [key] to the last unused address of
+     * [wallet]. [key] can be a WIF or a BIP38 encrypted key, which requires a [password]. The
+     * transaction still has to be broadcast.
+     */
+    fun sweep_private_key(wallet: WalletPtr, key: String, fee_rate: Float, password: String?=null): SweepResponse {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.set("wallet", mapper.valueToTree<JsonNode>(wallet))
+        node.put("key", key)
+        node.put("fee_rate", fee_rate)
+        node.put("password", password)
+        val req = JsonRpc("sweep_private_key", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, SweepResponse::class.java)
+    }
+
+    /**
+     * Build an unsigned BIP127 proof of reserves over all the UTXOs of [wallet], or only [utxos],
+     * committing to the challenge [message]. The proof is signed with [sign] and can never be
+     * broadcast
+     */
+    fun create_reserve_proof(wallet: WalletPtr, message: String, utxos: List<String>?=null, psbt_format: PsbtFormat?=null, psbt_version: Int?=null): ReserveProof {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.set("wallet", mapper.valueToTree<JsonNode>(wallet))
+        node.put("message", message)
+        node.set("utxos", mapper.valueToTree<JsonNode>(utxos))
+        node.set("psbt_format", mapper.valueToTree<JsonNode>(psbt_format))
+        node.put("psbt_version", psbt_version)
+        val req = JsonRpc("create_reserve_proof", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, ReserveProof::class.java)
+    }
+
+    /**
+     * Verify a finalized proof of reserves for [message] against the current UTXOs of [wallet],
+     * usually a watch-only wallet of the prover's descriptors, and return the proven amount.
+     * [wallet] is synced first, so that coins spent since its last sync are not counted. Fails
+     * with an `InvalidReserveProof` error when the proof is invalid, including when any of its
+     * signatures doesn't use SIGHASH_ALL since it could be replayed for another challenge. Fails
+     * with an `Unsupported` error on Android, where script verification is not available
+     */
+    fun verify_reserve_proof(wallet: WalletPtr, psbt: String, message: String): ProvenReserves =
+        verify_reserve_proof(wallet, mapper.valueToTree<JsonNode>(psbt), message)
+
+    fun verify_reserve_proof(wallet: WalletPtr, psbt: ByteArray, message: String): ProvenReserves =
+        verify_reserve_proof(wallet, psbtBytesNode(psbt), message)
+
+    private fun verify_reserve_proof(wallet: WalletPtr, psbt: JsonNode, message: String): ProvenReserves {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.set("wallet", mapper.valueToTree<JsonNode>(wallet))
+        node.set("psbt", psbt)
+        node.put("message", message)
+        val req = JsonRpc("verify_reserve_proof", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, ProvenReserves::class.java)
+    }
+
+    /**
+     * Sign [message] with the key behind [address] of [wallet], as a BIP137 signature by default.
+     * BIP137 signatures need the private key in the wallet, BIP322 ones also work with external
+     * signers
+     */
+    fun sign_message(wallet: WalletPtr, address: String, message: String, format: MessageFormat?=null): SignedMessage {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.set("wallet", mapper.valueToTree<JsonNode>(wallet))
+        node.put("address", address)
+        node.put("message", message)
+        node.set("format", mapper.valueToTree<JsonNode>(format))
+        val req = JsonRpc("sign_message", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, SignedMessage::class.java)
+    }
+
+    /**
+     * Verify a BIP137 or BIP322 [signature] of [message] for [address], detecting its format.
+     * BIP322 signatures fail with an `Unsupported` error on Android, where script verification is
+     * not available, and for proofs of funds with more than one input.
+     */
+    fun verify_message(address: String, message: String, signature: String): MessageVerification {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.put("address", address)
+        node.put("message", message)
+        node.put("signature", signature)
+        val req = JsonRpc("verify_message", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, MessageVerification::class.java)
+    }
+
+    /**
+     * Register a signer on [wallet] whose signatures are produced by [signer], for the keys of the
+     * master key with the given [fingerprint]. Calls failing because [signer] threw report the
+     * description of its exception. Calls on a wallet are serialized, so [signer] must not call
+     * back into [Lib] with the same wallet or it deadlocks
+     */
+    fun add_external_signer(wallet: WalletPtr, fingerprint: String, signer: ExternalSigner, keychain: Keychain?=null, ordering: Int?=null) {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.set("wallet", mapper.valueToTree<JsonNode>(wallet))
+        node.put("fingerprint", fingerprint)
+        node.set("keychain", mapper.valueToTree<JsonNode>(keychain))
+        node.put("ordering", ordering)
+        val req = JsonRpc("add_external_signer", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call_with_signer(reqString, signer)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+    }
+
+    /**
+     * Sign a PSBT with an xprv or WIF key that isn't part of a wallet. Extended keys are matched
+     * against the fingerprints and paths in the PSBT, [origin] (`[fingerprint/path]`) is needed
+     * if [key] is not a master key. Inputs asking for a sighash other than `SIGHASH_ALL` are only
+     * signed with [allow_all_sighashes]
+     */
+    fun sign_psbt_with_key(psbt: String, key: String, origin: String?=null, psbt_format: PsbtFormat?=null, psbt_version: Int?=null, allow_all_sighashes: Boolean?=null): SignPsbtWithKeyResponse =
+        sign_psbt_with_key(mapper.valueToTree<JsonNode>(psbt), key, origin, psbt_format, psbt_version, allow_all_sighashes)
+
+    fun sign_psbt_with_key(psbt: ByteArray, key: String, origin: String?=null, psbt_format: PsbtFormat?=null, psbt_version: Int?=null, allow_all_sighashes: Boolean?=null): SignPsbtWithKeyResponse =
+        sign_psbt_with_key(psbtBytesNode(psbt), key, origin, psbt_format, psbt_version, allow_all_sighashes)
+
+    private fun sign_psbt_with_key(psbt: JsonNode, key: String, origin: String?, psbt_format: PsbtFormat?, psbt_version: Int?, allow_all_sighashes: Boolean?): SignPsbtWithKeyResponse {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.set("psbt", psbt)
+        node.set("psbt_format", mapper.valueToTree<JsonNode>(psbt_format))
+        node.put("psbt_version", psbt_version)
+        node.put("key", key)
+        node.put("origin", origin)
+        node.set("allow_all_sighashes", mapper.valueToTree<JsonNode>(allow_all_sighashes))
+        val req = JsonRpc("sign_psbt_with_key", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, SignPsbtWithKeyResponse::class.java)
+    }
+
+    /**
+     * Convert a PSBT to [psbt_version] 0 (BIP174) or 2 (BIP370). Every PSBT parameter accepts both
+     * versions, and the functions returning PSBTs take a `psbt_version` to pick the output version.
+     * It defaults to the version of the PSBT they were given, or 0 for new PSBTs
+     */
+    fun convert_psbt(psbt: String, psbt_version: Int, psbt_format: PsbtFormat?=null): EncodedPsbt =
+        convert_psbt(mapper.valueToTree<JsonNode>(psbt), psbt_version, psbt_format)
+
+    fun convert_psbt(psbt: ByteArray, psbt_version: Int, psbt_format: PsbtFormat?=null): EncodedPsbt =
+        convert_psbt(psbtBytesNode(psbt), psbt_version, psbt_format)
+
+    private fun convert_psbt(psbt: JsonNode, psbt_version: Int, psbt_format: PsbtFormat?): EncodedPsbt {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.set("psbt", psbt)
+        node.put("psbt_version", psbt_version)
+        node.set("psbt_format", mapper.valueToTree<JsonNode>(psbt_format))
+        val req = JsonRpc("convert_psbt", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, EncodedPsbt::class.java)
+    }
+
+    /**
+     * Combine PSBTs of the same transaction, each one can be a base64 or hex String or a ByteArray
+     */
+    fun combine_psbts(psbts: List<Any>, psbt_format: PsbtFormat?=null, psbt_version: Int?=null): EncodedPsbt {
+        val node = JsonNodeFactory.instance.objectNode()
+        val psbtsNode = node.putArray("psbts")
+        psbts.forEach {
+            psbtsNode.add(if (it is ByteArray) psbtBytesNode(it) else mapper.valueToTree<JsonNode>(it))
+        }
+        node.set("psbt_format", mapper.valueToTree<JsonNode>(psbt_format))
+        node.put("psbt_version", psbt_version)
+        val req = JsonRpc("combine_psbts", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, EncodedPsbt::class.java)
+    }
+
+    /**
+     * Split a PSBT in `ur:crypto-psbt` parts to be shown as an animated QR code. Returns at least
+     * [min_parts] parts, the ones after the first `fragment_count` are fountain-coded
+     */
+    fun encode_ur_psbt(psbt: String, max_fragment_length: Int?=null, min_parts: Int?=null): EncodeUrPsbtResponse =
+        encode_ur_psbt(mapper.valueToTree<JsonNode>(psbt), max_fragment_length, min_parts)
+
+    fun encode_ur_psbt(psbt: ByteArray, max_fragment_length: Int?=null, min_parts: Int?=null): EncodeUrPsbtResponse =
+        encode_ur_psbt(psbtBytesNode(psbt), max_fragment_length, min_parts)
+
+    private fun encode_ur_psbt(psbt: JsonNode, max_fragment_length: Int?, min_parts: Int?): EncodeUrPsbtResponse {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.set("psbt", psbt)
+        node.put("max_fragment_length", max_fragment_length)
+        node.put("min_parts", min_parts)
+        val req = JsonRpc("encode_ur_psbt", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, EncodeUrPsbtResponse::class.java)
+    }
+
+    fun new_ur_decoder(): UrDecoderPtr {
+        val req = JsonRpc("new_ur_decoder", JsonNodeFactory.instance.objectNode())
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, UrDecoderPtr::class.java)
+    }
+
+    /**
+     * Feed a scanned `ur:crypto-psbt` part to [decoder], the PSBT is returned once complete
+     */
+    fun receive_ur_part(decoder: UrDecoderPtr, part: String, psbt_format: PsbtFormat?=null, psbt_version: Int?=null): ReceiveUrPartResponse {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.set("decoder", mapper.valueToTree<JsonNode>(decoder))
+        node.put("part", part)
+        node.set("psbt_format", mapper.valueToTree<JsonNode>(psbt_format))
+        node.put("psbt_version", psbt_version)
+        val req = JsonRpc("receive_ur_part", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, ReceiveUrPartResponse::class.java)
+    }
+
+    fun free_ur_decoder(decoder: UrDecoderPtr) {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.set("decoder", mapper.valueToTree<JsonNode>(decoder))
+        val req = JsonRpc("free_ur_decoder", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+    }
+
+    /**
+     * Generate a new mnemonic with [mnemonicWordCount] words (12, 15, 18, 21 or 24)
+     */
+    fun generate_extended_key(
+        network: Network,
+        mnemonicWordCount: Int,
+        password: String?,
+        language: MnemonicLanguage?=null,
+        entropy: String?=null,
+        entropySource: EntropySource?=null,
+        mixSystemEntropy: Boolean?=null
+    ): ExtendedKey {
         val node = JsonNodeFactory.instance.objectNode()
         node.set("network", mapper.valueToTree<JsonNode>(network))
         node.put("word_count", mnemonicWordCount)
         node.put("password", password)
+        node.set("language", mapper.valueToTree<JsonNode>(language))
+        node.put("entropy", entropy)
+        node.set("entropy_source", mapper.valueToTree<JsonNode>(entropySource))
+        node.put("mix_system_entropy", mixSystemEntropy)
         val req = JsonRpc("generate_extended_key", node)
         val reqString = mapper.writeValueAsString(req)
         val resString = call(reqString)
@@ -223,11 +551,12 @@ class Lib {
         return mapper.treeToValue(json, ExtendedKey::class.java)
     }
 
-    fun restore_extended_key(network: Network, mnemonic: String, password: String?): ExtendedKey {
+    fun restore_extended_key(network: Network, mnemonic: String, password: String?, language: MnemonicLanguage?=null): ExtendedKey {
         val node = JsonNodeFactory.instance.objectNode()
         node.set("network", mapper.valueToTree<JsonNode>(network))
         node.put("mnemonic", mnemonic)
         node.put("password", password)
+        node.set("language", mapper.valueToTree<JsonNode>(language))
         val req = JsonRpc("restore_extended_key", node)
         val reqString = mapper.writeValueAsString(req)
         val resString = call(reqString)
@@ -237,4 +566,324 @@ class Lib {
         }
         return mapper.treeToValue(json, ExtendedKey::class.java)
     }
+
+    fun descriptor_template(network: Network, template: TemplateParams): TemplateDescriptors {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.set("network", mapper.valueToTree<JsonNode>(network))
+        node.set("template", mapper.valueToTree<JsonNode>(template))
+        val req = JsonRpc("descriptor_template", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, TemplateDescriptors::class.java)
+    }
+
+    /**
+     * Sync the first [accounts] accounts of each template of a master key, given as [mnemonic] or
+     * [xprv], and return the ones with any transaction. The accounts are returned as public
+     * descriptors, to be imported as watch-only wallets or combined with the master key.
+     */
+    fun discover_accounts(
+        network: Network,
+        electrumUrl: String,
+        electrumStopGap: Long,
+        mnemonic: String?=null,
+        xprv: String?=null,
+        password: String?=null,
+        language: MnemonicLanguage?=null,
+        templates: List<Template>?=null,
+        accounts: Int?=null,
+        electrumProxy: String?=null,
+        electrumRetry: Int=5,
+        electrumTimeout: Int?=null
+    ): DiscoveredAccounts {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.set("network", mapper.valueToTree<JsonNode>(network))
+        node.put("mnemonic", mnemonic)
+        node.put("xprv", xprv)
+        node.put("password", password)
+        node.set("language", mapper.valueToTree<JsonNode>(language))
+        node.set("templates", mapper.valueToTree<JsonNode>(templates))
+        node.put("accounts", accounts)
+        node.put("electrum_url", electrumUrl)
+        node.put("electrum_proxy", electrumProxy)
+        node.put("electrum_retry", electrumRetry)
+        node.put("electrum_timeout", electrumTimeout)
+        node.put("electrum_stop_gap", electrumStopGap)
+        val req = JsonRpc("discover_accounts", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, DiscoveredAccounts::class.java)
+    }
+
+    fun multisig_descriptor(
+        network: Network,
+        threshold: Int,
+        scriptType: MultisigScriptType?,
+        cosigners: List<String>,
+        xprv: String?=null,
+        mnemonic: String?=null,
+        password: String?=null,
+        language: MnemonicLanguage?=null,
+        account: Int?=null
+    ): MultisigDescriptors {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.set("network", mapper.valueToTree<JsonNode>(network))
+        node.put("threshold", threshold)
+        node.set("script_type", mapper.valueToTree<JsonNode>(scriptType))
+        node.set("cosigners", mapper.valueToTree<JsonNode>(cosigners))
+        node.put("xprv", xprv)
+        node.put("mnemonic", mnemonic)
+        node.put("password", password)
+        node.set("language", mapper.valueToTree<JsonNode>(language))
+        node.put("account", account)
+        val req = JsonRpc("multisig_descriptor", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, MultisigDescriptors::class.java)
+    }
+
+    fun bip85(
+        application: Bip85Application,
+        index: Int,
+        xprv: String?=null,
+        mnemonic: String?=null,
+        password: String?=null,
+        language: MnemonicLanguage?=null,
+        network: Network?=null
+    ): Bip85Derived {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.set("application", mapper.valueToTree<JsonNode>(application))
+        node.put("index", index)
+        node.put("xprv", xprv)
+        node.put("mnemonic", mnemonic)
+        node.put("password", password)
+        node.set("language", mapper.valueToTree<JsonNode>(language))
+        node.set("network", mapper.valueToTree<JsonNode>(network))
+        val req = JsonRpc("bip85", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, Bip85Derived::class.java)
+    }
+
+    fun verify_passphrase(
+        network: Network,
+        mnemonic: String,
+        password: String?,
+        language: MnemonicLanguage?=null,
+        expectedFingerprint: String?=null
+    ): PassphraseCheck {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.set("network", mapper.valueToTree<JsonNode>(network))
+        node.put("mnemonic", mnemonic)
+        node.put("password", password)
+        node.set("language", mapper.valueToTree<JsonNode>(language))
+        node.put("expected_fingerprint", expectedFingerprint)
+        val req = JsonRpc("verify_passphrase", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, PassphraseCheck::class.java)
+    }
+
+    fun bip38_encrypt(wif: String, password: String): Bip38Encrypted {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.put("wif", wif)
+        node.put("password", password)
+        val req = JsonRpc("bip38_encrypt", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, Bip38Encrypted::class.java)
+    }
+
+    /** Fails with a `WrongPassword` error when [password] doesn't decrypt [key] */
+    fun bip38_decrypt(key: String, password: String): Bip38Decrypted {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.put("key", key)
+        node.put("password", password)
+        val req = JsonRpc("bip38_decrypt", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, Bip38Decrypted::class.java)
+    }
+
+    fun seed_xor_split(mnemonic: String, parts: Int, language: MnemonicLanguage?=null): SeedXorParts {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.put("mnemonic", mnemonic)
+        node.put("parts", parts)
+        node.set("language", mapper.valueToTree<JsonNode>(language))
+        val req = JsonRpc("seed_xor_split", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, SeedXorParts::class.java)
+    }
+
+    fun seed_xor_combine(parts: List<String>, language: MnemonicLanguage?=null): SeedXorCombined {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.set("parts", mapper.valueToTree<JsonNode>(parts))
+        node.set("language", mapper.valueToTree<JsonNode>(language))
+        val req = JsonRpc("seed_xor_combine", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, SeedXorCombined::class.java)
+    }
+
+    /**
+     * Split [masterSecret] or [mnemonic] in SLIP-39 shares, [groupThreshold] of the [groups] are
+     * needed to recover it.
+     *
+     * The shares of a [mnemonic] hold its BIP39 entropy, not its seed: Trezor and other SLIP-39
+     * wallets derive their keys from the master secret directly, so restoring the shares there
+     * gives a different wallet. Recover the mnemonic with [slip39_combine] instead.
+     */
+    fun slip39_split(
+        groupThreshold: Int,
+        groups: List<Slip39Group>,
+        masterSecret: String?=null,
+        mnemonic: String?=null,
+        language: MnemonicLanguage?=null,
+        passphrase: String?=null,
+        iterationExponent: Int?=null
+    ): Slip39Shares {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.put("group_threshold", groupThreshold)
+        node.set("groups", mapper.valueToTree<JsonNode>(groups))
+        node.put("master_secret", masterSecret)
+        node.put("mnemonic", mnemonic)
+        node.set("language", mapper.valueToTree<JsonNode>(language))
+        node.put("passphrase", passphrase)
+        node.put("iteration_exponent", iterationExponent)
+        val req = JsonRpc("slip39_split", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, Slip39Shares::class.java)
+    }
+
+    /**
+     * Recover the master secret from SLIP-39 [shares], along with the BIP39 mnemonic encoding it
+     * whenever the secret length allows it, even if it was not split from a mnemonic.
+     */
+    fun slip39_combine(shares: List<String>, passphrase: String?=null, language: MnemonicLanguage?=null): Slip39Recovered {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.set("shares", mapper.valueToTree<JsonNode>(shares))
+        node.put("passphrase", passphrase)
+        node.set("language", mapper.valueToTree<JsonNode>(language))
+        val req = JsonRpc("slip39_combine", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, Slip39Recovered::class.java)
+    }
+
+    fun convert_extended_key(key: String, version: KeyVersion): ConvertedKey {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.put("key", key)
+        node.set("version", mapper.valueToTree<JsonNode>(version))
+        val req = JsonRpc("convert_extended_key", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, ConvertedKey::class.java)
+    }
+
+    /**
+     * Derive [xprv] or [mnemonic] at [path]. [xprv] must be a master key, the key origin of the
+     * derived key can't be computed from a child key
+     */
+    fun derive_extended_key(
+        path: String,
+        xprv: String?=null,
+        mnemonic: String?=null,
+        password: String?=null,
+        language: MnemonicLanguage?=null,
+        network: Network?=null
+    ): DerivedKey {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.put("path", path)
+        node.put("xprv", xprv)
+        node.put("mnemonic", mnemonic)
+        node.put("password", password)
+        node.set("language", mapper.valueToTree<JsonNode>(language))
+        node.set("network", mapper.valueToTree<JsonNode>(network))
+        val req = JsonRpc("derive_extended_key", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, DerivedKey::class.java)
+    }
+
+    fun validate_mnemonic(mnemonic: String, language: MnemonicLanguage?=null): MnemonicValidation {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.put("mnemonic", mnemonic)
+        node.set("language", mapper.valueToTree<JsonNode>(language))
+        val req = JsonRpc("validate_mnemonic", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, MnemonicValidation::class.java)
+    }
+
+    fun mnemonic_final_words(mnemonic: String, language: MnemonicLanguage?=null): MnemonicFinalWords {
+        val node = JsonNodeFactory.instance.objectNode()
+        node.put("mnemonic", mnemonic)
+        node.set("language", mapper.valueToTree<JsonNode>(language))
+        val req = JsonRpc("mnemonic_final_words", node)
+        val reqString = mapper.writeValueAsString(req)
+        val resString = call(reqString)
+        val json: JsonNode = mapper.readValue(resString)
+        if (json.has("error")) {
+            throw Exception(json.get("error").asText())
+        }
+        return mapper.treeToValue(json, MnemonicFinalWords::class.java)
+    }
 }
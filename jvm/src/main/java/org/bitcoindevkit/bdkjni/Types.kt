@@ -7,12 +7,67 @@ enum class Network {
     testnet,
 }
 
+/** BIP39 wordlist, English is used when not specified */
+enum class MnemonicLanguage {
+    english,
+    chinese_simplified,
+    chinese_traditional,
+    french,
+    italian,
+    japanese,
+    korean,
+    spanish,
+}
+
+enum class EntropySource {
+    hex,
+    dice,
+    coin,
+}
+
+enum class Keychain {
+    External,
+    Internal,
+}
+
+/**
+ * Signer living on the JVM side, eg. backed by the Android Keystore or an HSM
+ */
+interface ExternalSigner {
+    /**
+     * Sign [sighash] (hex) with the key derived at [path] from the master key with [fingerprint].
+     * Must return the hex DER-encoded signature, without the sighash type byte. Throwing fails the
+     * signing call with the description of the exception. Runs while the wallet is locked, using
+     * the same wallet from here deadlocks
+     */
+    fun sign(sighash: String, fingerprint: String, path: String): String
+}
+
+/**
+ * Encoding of the PSBTs returned by the library. PSBTs sent to it can be a base64 or hex String or a
+ * ByteArray, the format is detected automatically
+ */
+enum class PsbtFormat {
+    base64,
+    hex,
+    binary,
+}
+
+/**
+ * PSBT returned by the library. [psbt] is hex for [PsbtFormat.hex] and base64 otherwise, [psbt_bytes]
+ * is only set for [PsbtFormat.binary]
+ */
+data class EncodedPsbt(
+    val psbt: String,
+    val psbt_bytes: ByteArray? = null
+)
+
 data class WalletConstructor(
     var name: String,
     var network: Network,
     var path: String,
-    var descriptor: String,
-    var change_descriptor: String?,
+    var descriptor: String? = null,
+    var change_descriptor: String? = null,
 
     /**
      * URL of the Electrum server (such as ElectrumX, Esplora, BWT) may start with `ssl://` or `tcp://` and include a port
@@ -27,6 +82,162 @@ data class WalletConstructor(
     var electrum_timeout: Int?,
     /** Stop searching addresses for transactions after finding an unused gap of this length */
     var electrum_stop_gap: Long,
+
+    /**
+     * Build the descriptors from a template instead of `descriptor` and `change_descriptor`, such
+     * as `TemplateParams(mnemonic = ..., password = ..., account = ...)` to derive them from a
+     * mnemonic without handling the xprv
+     */
+    var template: TemplateParams? = null,
+)
+
+enum class Template {
+    bip44,
+    bip49,
+    bip84,
+}
+
+/**
+ * The key of a descriptor template, either a master key as [xprv] or [mnemonic] or an account
+ * level [xpub] with its [origin] formatted as `[fingerprint/path]`
+ */
+data class TemplateParams(
+    /** Inferred from the version of ypub and zpub keys when null, defaults to bip84 for master keys */
+    val template: Template? = null,
+    val xprv: String? = null,
+    val mnemonic: String? = null,
+    val password: String? = null,
+    val language: MnemonicLanguage? = null,
+    /** Account number, only used with a master key */
+    val account: Int? = null,
+    /** Account level xpub, or its SLIP-132 equivalent */
+    val xpub: String? = null,
+    val origin: String? = null,
+)
+
+data class TemplateAddress(
+    val template: Template,
+    val path: String,
+    val address: String
+)
+
+data class PassphraseCheck(
+    val fingerprint: String,
+    /** Whether [fingerprint] is the expected one, null when no fingerprint was expected */
+    val matches: Boolean?,
+    /** First receive address of account 0 for each template */
+    val addresses: List<TemplateAddress>
+)
+
+data class SeedXorParts(
+    val parts: List<String>
+)
+
+data class SeedXorCombined(
+    val mnemonic: String
+)
+
+/** A SLIP-39 group, [threshold] of its [count] shares are needed to recover it */
+data class Slip39Group(
+    val threshold: Int,
+    val count: Int
+)
+
+data class Slip39Shares(
+    /** Share mnemonics of each group */
+    val groups: List<List<String>>
+)
+
+data class Slip39Recovered(
+    val master_secret: String,
+    /**
+     * The BIP39 mnemonic encoding the master secret, returned whatever was split, and only missing
+     * for secret lengths BIP39 doesn't support (e.g. 18 bytes)
+     */
+    val mnemonic: String?
+)
+
+enum class Bip85Type {
+    bip39,
+    hex,
+    xprv,
+    wif,
+}
+
+/** A BIP85 application, [word_count] and [language] are used by `bip39` and [num_bytes] by `hex` */
+data class Bip85Application(
+    val type: Bip85Type,
+    val word_count: Int? = null,
+    val language: MnemonicLanguage? = null,
+    val num_bytes: Int? = null,
+)
+
+data class Bip85Derived(
+    /** Mnemonic, hex entropy, xprv or WIF depending on the application */
+    val derived: String,
+    val path: String
+)
+
+/** SLIP-132 extended key versions, named after their mainnet public key prefix */
+enum class KeyVersion {
+    /** P2PKH or P2SH, tpub on testnet */
+    xpub,
+    /** P2WPKH nested in P2SH, upub on testnet */
+    ypub,
+    /** P2WPKH, vpub on testnet */
+    zpub,
+    /** Multisig P2WSH nested in P2SH, Upub on testnet */
+    Ypub,
+    /** Multisig P2WSH, Vpub on testnet */
+    Zpub,
+}
+
+data class ConvertedKey(
+    val key: String,
+    val original_version: KeyVersion,
+    val private: Boolean
+)
+
+enum class MultisigScriptType {
+    p2wsh,
+    p2sh_p2wsh,
+}
+
+data class MultisigDescriptors(
+    /** Including the checksum, with our own key as an xprv if we have one */
+    val descriptor: String,
+    val change_descriptor: String,
+    val checksum: String,
+    val change_checksum: String,
+    /** Including the checksum, with every key as an xpub, to be shared with the cosigners */
+    val public_descriptor: String,
+    val public_change_descriptor: String,
+    val public_checksum: String,
+    val public_change_checksum: String,
+    /** Our own key as `[fingerprint/path]xpub`, to be shared with the cosigners */
+    val key_origin: String?
+)
+
+data class UsedAccount(
+    val template: Template,
+    val account: Int,
+    /** Public descriptor, with the origin of the account xpub */
+    val descriptor: String,
+    val change_descriptor: String,
+    val xpub: String,
+    /** Origin of [xpub], as `[fingerprint/path]` */
+    val origin: String,
+    val balance: Long,
+    val transactions: Int
+)
+
+data class DiscoveredAccounts(
+    val accounts: List<UsedAccount>
+)
+
+data class TemplateDescriptors(
+    val descriptor: String,
+    val change_descriptor: String
 )
 
 data class TxOut(
@@ -59,16 +270,46 @@ data class ConfirmationTime(
 
 data class CreateTxResponse(
     val details: TransactionDetails,
-    val psbt: String
+    val psbt: String,
+    /** Only set for [PsbtFormat.binary] */
+    val psbt_bytes: ByteArray? = null
 )
 
 data class SignResponse(
     val psbt: String,
-    val finalized: Boolean
+    val finalized: Boolean,
+    /** Only set for [PsbtFormat.binary] */
+    val psbt_bytes: ByteArray? = null
+)
+
+data class ReserveProof(
+    /** Unsigned proof, sign it with [Lib.sign] */
+    val psbt: String,
+    /** Only set for [PsbtFormat.binary] */
+    val psbt_bytes: ByteArray? = null
+)
+
+data class ProvenReserves(
+    val amount: Long
+)
+
+data class SignPsbtWithKeyResponse(
+    val psbt: String,
+    /** Only set for [PsbtFormat.binary] */
+    val psbt_bytes: ByteArray? = null,
+    /** Number of signatures added to the PSBT */
+    val signatures: Int
 )
 
+// FIXME: all Longs should be unsigned
 data class RawTransaction(
-    val transaction: String
+    val transaction: String,
+    val txid: String,
+    val wtxid: String,
+    val vsize: Long,
+    val weight: Long,
+    /** null if the PSBT doesn't carry the previous outputs of all its inputs */
+    val fee: Long?,
 )
 
 data class Txid(
@@ -80,10 +321,96 @@ data class PublicDescriptorsResponse(
     val internal: String?
 )
 
+data class EncodeUrPsbtResponse(
+    val parts: List<String>,
+    val fragment_count: Int
+)
+
+data class ReceiveUrPartResponse(
+    val complete: Boolean,
+    /** Estimated progress between 0 and 1 */
+    val progress: Float,
+    val received: Int,
+    val fragment_count: Int?,
+    /** Set once complete */
+    val psbt: String?,
+    /** Only set for [PsbtFormat.binary] */
+    val psbt_bytes: ByteArray? = null
+)
+
+data class SweepResponse(
+    val transaction: String,
+    val txid: String,
+    /** Total value of the swept outputs */
+    val amount: Long,
+    val fee: Long
+)
+
+enum class MessageFormat {
+    bip137,
+    bip322_simple,
+    bip322_full,
+}
+
+data class SignedMessage(
+    /** Base64 encoded */
+    val signature: String,
+    val format: MessageFormat
+)
+
+data class MessageVerification(
+    val valid: Boolean,
+    /** Detected from the signature */
+    val format: MessageFormat
+)
+
+data class Bip38Encrypted(
+    val key: String
+)
+
+data class Bip38Decrypted(
+    val wif: String,
+    val compressed: Boolean
+)
+
 data class ExtendedKey(
     val mnemonic: String,
     val xprv: String,
     val fingerprint: String,
+    /** Entropy encoded by the mnemonic, in hex */
+    val entropy: String,
+)
+
+data class InvalidWord(
+    /** Position of the word in the phrase, starting from 0 */
+    val index: Int,
+    val word: String,
+    val suggestions: List<String>
+)
+
+data class DerivedKey(
+    val xprv: String,
+    val xpub: String,
+    /** Fingerprint of the master key */
+    val fingerprint: String,
+    val path: String,
+    /** `[fingerprint/path]xpub`, ready to be used in a descriptor */
+    val key_origin: String
+)
+
+data class MnemonicFinalWords(
+    val language: MnemonicLanguage,
+    val words: List<String>
+)
+
+data class MnemonicValidation(
+    val valid: Boolean,
+    val language: MnemonicLanguage?,
+    val word_count: Int,
+    val word_count_valid: Boolean,
+    val invalid_words: List<InvalidWord>,
+    /** Null when the checksum can't be verified because of invalid words or word count */
+    val checksum_valid: Boolean?
 )
 
 // FIXME: Those should be decleared as UBytes, but jackson doesn't know how to parse them. so we use Ints that are larger and won't overflow to negative
@@ -91,3 +418,8 @@ data class WalletPtr(
     var raw: List<Int>,
     var id: List<Int>
 )
+
+data class UrDecoderPtr(
+    var raw: List<Int>,
+    var id: List<Int>
+)
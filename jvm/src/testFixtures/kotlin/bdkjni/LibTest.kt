@@ -4,11 +4,13 @@ import kotlinx.coroutines.Dispatchers
 import kotlinx.coroutines.flow.*
 import kotlinx.coroutines.runBlocking
 import org.junit.Assert.*
+import org.junit.Assume.assumeTrue
 import org.junit.Ignore
 import org.junit.Test
 import org.slf4j.Logger
 import org.slf4j.LoggerFactory
 import java.io.File
+import java.security.MessageDigest
 import java.util.*
 
 /**
@@ -31,6 +33,9 @@ abstract class LibTest {
 
     abstract fun getDataDir(): String
 
+    /** Whether BIP322 signatures and proofs of reserves can be verified, not on Android */
+    open val scriptVerification = true
+
     fun cleanupDataDir(dir: String) {
         File(dir).deleteRecursively()
     }
@@ -57,6 +62,24 @@ abstract class LibTest {
         return wallet
     }
 
+    /** Wallet watching the external descriptor of [wallet], without syncing it */
+    fun watchOnlyConstructor(dir: String, wallet: WalletPtr): WalletPtr {
+        return Lib().constructor(
+            WalletConstructor(
+                "watch-only",
+                Network.regtest,
+                dir,
+                Lib().public_descriptors(wallet).external,
+                null,
+                "tcp://electrum.blockstream.info:60001",
+                null,
+                5,
+                null,
+                100,
+            )
+        )
+    }
+
     @Test
     fun newAddress() {
         val dir = getDataDir()
@@ -91,6 +114,355 @@ abstract class LibTest {
         }
     }
 
+    @Test
+    fun binaryPsbt() {
+        val dir = getDataDir()
+        val wallet = constructor(dir)
+        try {
+            val address = Lib().get_new_address(wallet)
+            val created = Lib().create_tx(wallet, 1.0F, listOf(Pair(address, "1000")), psbt_format = PsbtFormat.binary)
+            val unsigned = created.psbt_bytes!!
+            assertArrayEquals(Base64.getDecoder().decode(created.psbt), unsigned)
+            assertEquals(
+                unsigned.joinToString("") { "%02x".format(it) },
+                Lib().convert_psbt(unsigned, 0, PsbtFormat.hex).psbt
+            )
+
+            val v2 = Lib().convert_psbt(unsigned, 2, PsbtFormat.binary).psbt_bytes!!
+            assertArrayEquals(unsigned, Lib().convert_psbt(v2, 0, PsbtFormat.binary).psbt_bytes)
+
+            val signed = Lib().sign(wallet, unsigned, psbt_format = PsbtFormat.binary)
+            assertTrue(signed.finalized)
+            assertNull(Lib().sign(wallet, unsigned).psbt_bytes)
+            Lib().extract_psbt(wallet, signed.psbt_bytes!!)
+        } finally {
+            Lib().destructor(wallet)
+            cleanupDataDir(dir)
+        }
+    }
+
+    @Test
+    fun urPsbt() {
+        val dir = getDataDir()
+        val wallet = constructor(dir)
+        try {
+            val address = Lib().get_new_address(wallet)
+            val psbt = Lib().create_tx(wallet, 1.0F, listOf(Pair(address, "1000"))).psbt
+
+            val single = Lib().encode_ur_psbt(psbt, max_fragment_length = 10000)
+            assertEquals(1, single.parts.size)
+            val singleDecoder = Lib().new_ur_decoder()
+            try {
+                val received = Lib().receive_ur_part(singleDecoder, single.parts[0])
+                assertTrue(received.complete)
+                assertEquals(psbt, received.psbt)
+            } finally {
+                Lib().free_ur_decoder(singleDecoder)
+            }
+
+            val multi = Lib().encode_ur_psbt(psbt, max_fragment_length = 50, min_parts = 100)
+            assertTrue(multi.fragment_count > 1)
+            assertEquals(100, multi.parts.size)
+            val multiDecoder = Lib().new_ur_decoder()
+            try {
+                // Skip the first part, the mixed ones have to make up for it
+                val received = multi.parts.drop(1)
+                    .map { Lib().receive_ur_part(multiDecoder, it) }
+                    .first { it.complete }
+                assertEquals(multi.fragment_count, received.fragment_count)
+                assertEquals(psbt, received.psbt)
+            } finally {
+                Lib().free_ur_decoder(multiDecoder)
+            }
+        } finally {
+            Lib().destructor(wallet)
+            cleanupDataDir(dir)
+        }
+    }
+
+    @Test
+    fun extractPsbt() {
+        val dir = getDataDir()
+        val wallet = constructor(dir)
+        try {
+            val address = Lib().get_new_address(wallet)
+            val created = Lib().create_tx(wallet, 1.0F, listOf(Pair(address, "1000")))
+            val unsigned = created.psbt
+            val exception = assertThrows(Exception::class.java) {
+                Lib().extract_psbt(wallet, unsigned)
+            }
+            assertTrue(exception.message!!.contains("PsbtNotFinalized"))
+
+            val signed = Lib().sign(wallet, unsigned)
+            assertTrue(signed.finalized)
+            val extracted = Lib().extract_psbt(wallet, signed.psbt)
+            assertEquals(created.details.fee, extracted.fee)
+            assertEquals((extracted.weight + 3) / 4, extracted.vsize)
+            assertNotEquals(extracted.txid, extracted.wtxid)
+        } finally {
+            Lib().destructor(wallet)
+            cleanupDataDir(dir)
+        }
+    }
+
+    @Test
+    fun signOptions() {
+        val dir = getDataDir()
+        val watchOnlyDir = getDataDir()
+        val wallet = constructor(dir)
+        try {
+            val address = Lib().get_new_address(wallet)
+            val unsigned = Lib().create_tx(wallet, 1.0F, listOf(Pair(address, "1000"))).psbt
+
+            val notFinalized = Lib().sign(wallet, unsigned, try_finalize = false)
+            assertFalse(notFinalized.finalized)
+            assertThrows(Exception::class.java) {
+                Lib().extract_psbt(wallet, notFinalized.psbt)
+            }
+            // The partial signatures are kept, a wallet without keys can finalize them
+            val watchOnly = watchOnlyConstructor(watchOnlyDir, wallet)
+            try {
+                assertTrue(Lib().sign(watchOnly, notFinalized.psbt).finalized)
+            } finally {
+                Lib().destructor(watchOnly)
+            }
+
+            val withPartialSigs = Lib().sign(wallet, unsigned)
+            val withoutPartialSigs = Lib().sign(wallet, unsigned, remove_partial_sigs = true)
+            assertTrue(withoutPartialSigs.finalized)
+            assertTrue(withoutPartialSigs.psbt.length < withPartialSigs.psbt.length)
+
+            val anyoneCanPay = "SIGHASH_ALL|SIGHASH_ANYONECANPAY"
+            assertThrows(Exception::class.java) {
+                Lib().sign(wallet, unsigned, sighash_type = anyoneCanPay)
+            }
+            assertTrue(Lib().sign(wallet, unsigned, sighash_type = anyoneCanPay, allow_all_sighashes = true).finalized)
+            assertThrows(Exception::class.java) {
+                Lib().sign(wallet, unsigned, sighash_type = "SIGHASH_ALL|ANYONECANPAY", allow_all_sighashes = true)
+            }
+        } finally {
+            Lib().destructor(wallet)
+            cleanupDataDir(dir)
+            cleanupDataDir(watchOnlyDir)
+        }
+    }
+
+    @Test
+    fun externalSigner() {
+        val tprv = "tprv8ZgxMBicQKsPexGYyaFwnAsCXCjmz2FaTm6LtesyyihjbQE3gRMfXqQBXKM43DvC1UgRVv1qom1qFxNMSqVAs88qx9PhgFnfGVUdiiDf6j4"
+        val fingerprint = Lib().derive_extended_key("m", xprv = tprv).fingerprint
+        val dir = getDataDir()
+        val watchOnlyDir = getDataDir()
+        val rejectingDir = getDataDir()
+        val wallet = constructor(dir)
+        val watchOnly = watchOnlyConstructor(watchOnlyDir, wallet)
+        val rejecting = watchOnlyConstructor(rejectingDir, wallet)
+        try {
+            val address = Lib().get_new_address(wallet)
+            val unsigned = Lib().create_tx(wallet, 1.0F, listOf(Pair(address, "1000"))).psbt
+
+            Lib().add_external_signer(watchOnly, fingerprint, TestSigner(tprv))
+            val signed = Lib().sign(watchOnly, unsigned)
+            assertTrue(signed.finalized)
+            Lib().extract_psbt(wallet, signed.psbt)
+
+            Lib().add_external_signer(rejecting, fingerprint, object : ExternalSigner {
+                override fun sign(sighash: String, fingerprint: String, path: String): String {
+                    throw IllegalStateException("Rejected on the device")
+                }
+            })
+            val exception = assertThrows(Exception::class.java) {
+                Lib().sign(rejecting, unsigned)
+            }
+            assertTrue(exception.message!!.contains("ExternalSigner"))
+            assertTrue(exception.message!!.contains("Rejected on the device"))
+        } finally {
+            Lib().destructor(rejecting)
+            Lib().destructor(watchOnly)
+            Lib().destructor(wallet)
+            cleanupDataDir(rejectingDir)
+            cleanupDataDir(watchOnlyDir)
+            cleanupDataDir(dir)
+        }
+    }
+
+    @Test
+    fun signPsbtWithKey() {
+        val tprv = "tprv8ZgxMBicQKsPexGYyaFwnAsCXCjmz2FaTm6LtesyyihjbQE3gRMfXqQBXKM43DvC1UgRVv1qom1qFxNMSqVAs88qx9PhgFnfGVUdiiDf6j4"
+        val dir = getDataDir()
+        val watchOnlyDir = getDataDir()
+        val wallet = constructor(dir)
+        val watchOnly = watchOnlyConstructor(watchOnlyDir, wallet)
+        try {
+            val address = Lib().get_new_address(wallet)
+            val unsigned = Lib().create_tx(wallet, 1.0F, listOf(Pair(address, "1000"))).psbt
+
+            val signed = Lib().sign_psbt_with_key(unsigned, tprv)
+            assertTrue(signed.signatures > 0)
+            assertEquals(0, Lib().sign_psbt_with_key(signed.psbt, tprv).signatures)
+
+            // The watch-only wallet sets the sighash type of the inputs without signing them
+            val anyoneCanPay = Lib().sign(
+                watchOnly,
+                unsigned,
+                sighash_type = "SIGHASH_ALL|SIGHASH_ANYONECANPAY",
+                allow_all_sighashes = true
+            ).psbt
+            val exception = assertThrows(Exception::class.java) {
+                Lib().sign_psbt_with_key(anyoneCanPay, tprv)
+            }
+            assertTrue(exception.message!!.contains("NonStandardSighash"))
+            assertEquals(
+                signed.signatures,
+                Lib().sign_psbt_with_key(anyoneCanPay, tprv, allow_all_sighashes = true).signatures
+            )
+        } finally {
+            Lib().destructor(watchOnly)
+            Lib().destructor(wallet)
+            cleanupDataDir(watchOnlyDir)
+            cleanupDataDir(dir)
+        }
+    }
+
+    @Test
+    fun sweepEmptyKey() {
+        val dir = getDataDir()
+        val wallet = constructor(dir)
+        try {
+            val keys = Lib().generate_extended_key(Network.testnet, 12, null)
+            val wif = Lib().bip85(Bip85Application(Bip85Type.wif), 0, xprv = keys.xprv).derived
+            val exception = assertThrows(Exception::class.java) {
+                Lib().sweep_private_key(wallet, wif, 1.0F)
+            }
+            assertTrue(exception.message!!.contains("No funds to sweep"))
+
+            // Test vector from BIP38, with the wrong password
+            assertThrows(Exception::class.java) {
+                Lib().sweep_private_key(wallet, "6PRVWUbkzzsbcVac2qwfssoUJAN1Xhrg6bNk8J7Nzm5H7kxEbn2Nh2ZoGg", 1.0F, "wrong")
+            }
+        } finally {
+            Lib().destructor(wallet)
+            cleanupDataDir(dir)
+        }
+    }
+
+    @Test
+    fun reserveProof() {
+        assumeTrue(scriptVerification)
+        val dir = getDataDir()
+        val wallet = constructor(dir)
+        try {
+            val message = "Challenge from the auditor"
+            val proof = Lib().create_reserve_proof(wallet, message)
+            val unsigned = proof.psbt
+            val exception = assertThrows(Exception::class.java) {
+                Lib().verify_reserve_proof(wallet, unsigned, message)
+            }
+            assertTrue(exception.message!!.contains("PsbtNotFinalized"))
+
+            val signed = Lib().sign(wallet, unsigned)
+            assertTrue(signed.finalized)
+            val proven = Lib().verify_reserve_proof(wallet, signed.psbt, message)
+            assertEquals(Lib().get_balance(wallet), proven.amount)
+
+            val wrongMessage = assertThrows(Exception::class.java) {
+                Lib().verify_reserve_proof(wallet, signed.psbt, "Another challenge")
+            }
+            assertTrue(wrongMessage.message!!.contains("InvalidReserveProof"))
+        } finally {
+            Lib().destructor(wallet)
+            cleanupDataDir(dir)
+        }
+    }
+
+    /** Hex of the txid spent by the challenge input of a proof of reserves for [message] */
+    private fun challengeTxid(message: String): String {
+        val sha256 = MessageDigest.getInstance("SHA-256")
+        val hash = sha256.digest(sha256.digest("Proof-of-Reserves: $message".toByteArray()))
+        return hash.joinToString("") { "%02x".format(it) }
+    }
+
+    @Test
+    fun reserveProofReplay() {
+        assumeTrue(scriptVerification)
+        val dir = getDataDir()
+        val wallet = constructor(dir)
+        try {
+            val message = "Challenge from the auditor"
+            val proof = Lib().create_reserve_proof(wallet, message, psbt_format = PsbtFormat.hex)
+            val signed = Lib().sign(
+                wallet,
+                proof.psbt,
+                allow_all_sighashes = true,
+                sighash_type = "SIGHASH_ALL|SIGHASH_ANYONECANPAY",
+                psbt_format = PsbtFormat.hex
+            )
+            assertTrue(signed.finalized)
+
+            // ANYONECANPAY signatures stay valid when the challenge input is swapped
+            val replayed = signed.psbt.replace(challengeTxid(message), challengeTxid("Another challenge"))
+            assertNotEquals(signed.psbt, replayed)
+            val exception = assertThrows(Exception::class.java) {
+                Lib().verify_reserve_proof(wallet, replayed, "Another challenge")
+            }
+            assertTrue(exception.message!!.contains("InvalidReserveProof"))
+        } finally {
+            Lib().destructor(wallet)
+            cleanupDataDir(dir)
+        }
+    }
+
+    @Test
+    fun signMessage() {
+        val dir = getDataDir()
+        val wallet = constructor(dir)
+        try {
+            val address = Lib().get_new_address(wallet)
+            for (format in MessageFormat.values()) {
+                val signed = Lib().sign_message(wallet, address, "Hello World", format)
+                assertEquals(format, signed.format)
+                if (format != MessageFormat.bip137 && !scriptVerification) {
+                    continue
+                }
+
+                val verification = Lib().verify_message(address, "Hello World", signed.signature)
+                assertTrue(verification.valid)
+                assertEquals(format, verification.format)
+                assertFalse(Lib().verify_message(address, "Hello World!", signed.signature).valid)
+            }
+        } finally {
+            Lib().destructor(wallet)
+            cleanupDataDir(dir)
+        }
+    }
+
+    @Test
+    fun verifyMessageBip322() {
+        assumeTrue(scriptVerification)
+        // Test vector from BIP322
+        val address = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l"
+        val signature =
+            "AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI="
+        val verification = Lib().verify_message(address, "Hello World", signature)
+        assertTrue(verification.valid)
+        assertEquals(MessageFormat.bip322_simple, verification.format)
+        assertFalse(Lib().verify_message(address, "", signature).valid)
+    }
+
+    @Test
+    fun discoverAccountsUnused() {
+        val keys = Lib().generate_extended_key(Network.testnet, 12, null)
+        val discovered = Lib().discover_accounts(
+            Network.testnet,
+            "tcp://electrum.blockstream.info:60001",
+            10,
+            xprv = keys.xprv,
+            templates = listOf(Template.bip84),
+            accounts = 1
+        )
+        assertTrue(discovered.accounts.isEmpty())
+    }
+
     @Test
     fun sync() {
         val dir = getDataDir()
@@ -191,6 +563,303 @@ abstract class LibTest {
         assertEquals("tprv", keys.xprv.substring(0, 4))
     }
 
+    @Test
+    fun generate_key_word_counts() {
+        for (wordCount in listOf(12, 15, 18, 21, 24)) {
+            val keys = Lib().generate_extended_key(Network.testnet, wordCount, null)
+            assertEquals(wordCount, keys.mnemonic.split(' ').size)
+        }
+        assertThrows(Exception::class.java) {
+            Lib().generate_extended_key(Network.testnet, 13, null)
+        }
+    }
+
+    @Test
+    fun generate_restore_key_language() {
+        val keys = Lib().generate_extended_key(Network.testnet, 12, null, MnemonicLanguage.spanish)
+        val restored = Lib().restore_extended_key(Network.testnet, keys.mnemonic, null, MnemonicLanguage.spanish)
+        assertEquals(keys.xprv, restored.xprv)
+    }
+
+    @Test
+    fun generate_key_user_entropy() {
+        val keys = Lib().generate_extended_key(Network.testnet, 12, null, entropy = "00".repeat(16))
+        assertEquals("abandon ".repeat(11) + "about", keys.mnemonic)
+        assertEquals("00".repeat(16), keys.entropy)
+
+        val dice = "123456".repeat(17)
+        val diceKeys = Lib().generate_extended_key(Network.testnet, 24, null, entropy = dice, entropySource = EntropySource.dice)
+        assertEquals(diceKeys.xprv, Lib().generate_extended_key(Network.testnet, 24, null, entropy = dice, entropySource = EntropySource.dice).xprv)
+        val mixed = Lib().generate_extended_key(Network.testnet, 24, null, entropy = dice, entropySource = EntropySource.dice, mixSystemEntropy = true)
+        assertNotEquals(diceKeys.xprv, mixed.xprv)
+
+        assertThrows(Exception::class.java) {
+            Lib().generate_extended_key(Network.testnet, 24, null, entropy = "1234", entropySource = EntropySource.dice)
+        }
+    }
+
+    @Test
+    fun validate_mnemonic() {
+        val mnemonic =
+            "shell bid diary primary focus average truly secret lonely circle radar fall tank action place body wedding sponsor embody glue swing gauge shop penalty"
+        val valid = Lib().validate_mnemonic(mnemonic)
+        assertTrue(valid.valid)
+        assertEquals(MnemonicLanguage.english, valid.language)
+
+        val typo = Lib().validate_mnemonic(mnemonic.replace("diary", "diarry"))
+        assertFalse(typo.valid)
+        assertEquals(1, typo.invalid_words.size)
+        assertEquals(2, typo.invalid_words[0].index)
+        assertTrue(typo.invalid_words[0].suggestions.contains("diary"))
+        assertNull(typo.checksum_valid)
+
+        val checksum = Lib().validate_mnemonic("abandon ".repeat(12))
+        assertFalse(checksum.valid)
+        assertEquals(false, checksum.checksum_valid)
+    }
+
+    @Test
+    fun derive_key() {
+        val master =
+            "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi"
+        val xpub =
+            "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw"
+        val derived = Lib().derive_extended_key("m/0'", xprv = master)
+        assertEquals(xpub, derived.xpub)
+        assertEquals("3442193e", derived.fingerprint)
+        assertEquals("[3442193e/0']$xpub", derived.key_origin)
+        val exception = assertThrows(Exception::class.java) {
+            Lib().derive_extended_key("m/0", xprv = derived.xprv)
+        }
+        assertTrue(exception.message!!.contains("Expected a master key"))
+
+        val mnemonic =
+            "shell bid diary primary focus average truly secret lonely circle radar fall tank action place body wedding sponsor embody glue swing gauge shop penalty"
+        val keys = Lib().restore_extended_key(Network.testnet, mnemonic, null)
+        val fromMnemonic = Lib().derive_extended_key("m/84'/1'/0'", mnemonic = mnemonic, network = Network.testnet)
+        assertEquals(Lib().derive_extended_key("m/84'/1'/0'", xprv = keys.xprv).xprv, fromMnemonic.xprv)
+        assertEquals(keys.fingerprint, fromMnemonic.fingerprint)
+    }
+
+    @Test
+    fun descriptor_template() {
+        val mnemonic = "abandon ".repeat(11) + "about"
+
+        val private = Lib().descriptor_template(Network.testnet, TemplateParams(Template.bip84, mnemonic = mnemonic))
+        assertTrue(private.descriptor.startsWith("wpkh([73c5da0a/84'/1'/0']tprv"))
+        assertEquals(1, private.descriptor.count { it == '#' })
+        assertTrue(private.change_descriptor.contains("/1/*)#"))
+
+        val account = Lib().derive_extended_key("m/84'/1'/0'", mnemonic = mnemonic, network = Network.testnet)
+        val public = Lib().descriptor_template(
+            Network.testnet,
+            TemplateParams(Template.bip84, xpub = account.xpub, origin = "[73c5da0a/84'/1'/0']")
+        )
+        assertTrue(public.descriptor.startsWith("wpkh(${account.key_origin}/0/*)#"))
+
+        val bip49 = Lib().descriptor_template(Network.testnet, TemplateParams(Template.bip49, mnemonic = mnemonic, account = 3))
+        assertTrue(bip49.descriptor.startsWith("sh(wpkh([73c5da0a/49'/1'/3']tprv"))
+    }
+
+    @Test
+    fun constructorFromMnemonic() {
+        val mnemonic = "abandon ".repeat(11) + "about"
+        val dir = getDataDir()
+        val wallet = Lib().constructor(
+            WalletConstructor(
+                name = "mnemonic",
+                network = Network.testnet,
+                path = dir,
+                electrum_url = "tcp://electrum.blockstream.info:60001",
+                electrum_proxy = null,
+                electrum_retry = 5,
+                electrum_timeout = null,
+                electrum_stop_gap = 100,
+                template = TemplateParams(mnemonic = mnemonic, password = "TREZOR"),
+            )
+        )
+        try {
+            val check = Lib().verify_passphrase(Network.testnet, mnemonic, "TREZOR")
+            val bip84 = check.addresses.first { it.template == Template.bip84 }
+            assertEquals(bip84.address, Lib().get_new_address(wallet))
+        } finally {
+            Lib().destructor(wallet)
+            cleanupDataDir(dir)
+        }
+    }
+
+    @Test
+    fun verify_passphrase() {
+        val mnemonic = "abandon ".repeat(11) + "about"
+        val check = Lib().verify_passphrase(Network.testnet, mnemonic, null, expectedFingerprint = "73c5da0a")
+        assertEquals("73c5da0a", check.fingerprint)
+        assertEquals(true, check.matches)
+        assertEquals(3, check.addresses.size)
+        val bip84 = check.addresses.first { it.template == Template.bip84 }
+        assertEquals("m/84'/1'/0'/0/0", bip84.path)
+        assertTrue(bip84.address.startsWith("tb1q"))
+
+        val typo = Lib().verify_passphrase(Network.testnet, mnemonic, "TREZOR", expectedFingerprint = "73c5da0a")
+        assertEquals(false, typo.matches)
+        assertNull(Lib().verify_passphrase(Network.testnet, mnemonic, "TREZOR").matches)
+    }
+
+    @Test
+    fun bip38() {
+        // Test vectors from BIP38
+        val uncompressed = "6PRVWUbkzzsbcVac2qwfssoUJAN1Xhrg6bNk8J7Nzm5H7kxEbn2Nh2ZoGg"
+        val uncompressedWif = "5KN7MzqK5wt2TP1fQCYyHBtDrXdJuXbUzm4A9rKAteGu3Qi5CVR"
+        val compressed = "6PYNKZ1EAgYgmQfmNVamxyXVWHzK5s6DGhwP4J5o44cvXdoY7sRzhtpUeo"
+        val compressedWif = "L44B5gGEpqEDRS9vVPz7QT35jcBG2r3CZwSwQ4fCewXAhAhqGVpP"
+        val ecMultiplied = "6PfQu77ygVyJLZjfvMLyhLMQbYnu5uguoJJ4kMCLqWwPEdfpwANVS76gTX"
+
+        assertEquals(uncompressed, Lib().bip38_encrypt(uncompressedWif, "TestingOneTwoThree").key)
+        assertEquals(compressed, Lib().bip38_encrypt(compressedWif, "TestingOneTwoThree").key)
+
+        val decrypted = Lib().bip38_decrypt(compressed, "TestingOneTwoThree")
+        assertEquals(compressedWif, decrypted.wif)
+        assertTrue(decrypted.compressed)
+        assertEquals(uncompressedWif, Lib().bip38_decrypt(uncompressed, "TestingOneTwoThree").wif)
+        assertEquals(
+            "5K4caxezwjGCGfnoPTZ8tMcJBLB7Jvyjv4xxeacadhq8nLisLR2",
+            Lib().bip38_decrypt(ecMultiplied, "TestingOneTwoThree").wif
+        )
+
+        val exception = assertThrows(Exception::class.java) {
+            Lib().bip38_decrypt(compressed, "TestingOneTwoFour")
+        }
+        assertTrue(exception.message!!.contains("WrongPassword"))
+    }
+
+    @Test
+    fun seed_xor() {
+        val mnemonic =
+            "shell bid diary primary focus average truly secret lonely circle radar fall tank action place body wedding sponsor embody glue swing gauge shop penalty"
+        val parts = Lib().seed_xor_split(mnemonic, 3).parts
+        assertEquals(3, parts.size)
+        parts.forEach { assertTrue(Lib().validate_mnemonic(it).valid) }
+        assertEquals(mnemonic, Lib().seed_xor_combine(parts).mnemonic)
+        assertNotEquals(mnemonic, Lib().seed_xor_combine(parts.take(2)).mnemonic)
+    }
+
+    @Test
+    fun slip39() {
+        // Test vector from SLIP-39
+        val share =
+            "duckling enlarge academic academic agency result length solution fridge kidney coal piece deal husband erode duke ajar critical decision keyboard"
+        assertEquals("bb54aac4b89dc868ba37d9cc21b2cece", Lib().slip39_combine(listOf(share), "TREZOR").master_secret)
+
+        val mnemonic = "abandon ".repeat(11) + "about"
+        val groups = Lib().slip39_split(2, listOf(Slip39Group(1, 1), Slip39Group(2, 3)), mnemonic = mnemonic, passphrase = "test123").groups
+        assertEquals(3, groups[1].size)
+
+        val recovered = Lib().slip39_combine(listOf(groups[0][0], groups[1][0], groups[1][2]), "test123")
+        assertEquals(mnemonic, recovered.mnemonic)
+        assertEquals("00".repeat(16), recovered.master_secret)
+
+        assertThrows(Exception::class.java) {
+            Lib().slip39_combine(listOf(groups[0][0], groups[1][0]), "test123")
+        }
+    }
+
+    @Test
+    fun bip85() {
+        // Test vectors from BIP85
+        val master =
+            "xprv9s21ZrQH143K2LBWUUQRFXhucrQqBpKdRRxNVq2zBqsx8HVqFk2uYo8kmbaLLHRdqtQpUm98uKfu3vca1LqdGhUtyoFnCNkfmXRyPXLjbKb"
+
+        val mnemonic = Lib().bip85(Bip85Application(Bip85Type.bip39, word_count = 12), 0, xprv = master)
+        assertEquals("girl mad pet galaxy egg matter matrix prison refuse sense ordinary nose", mnemonic.derived)
+        assertEquals("m/83696968'/39'/0'/12'/0'", mnemonic.path)
+        assertEquals(
+            "Kzyv4uF39d4Jrw2W7UryTHwZr1zQVNk4dAFyqE6BuMrMh1Za7uhp",
+            Lib().bip85(Bip85Application(Bip85Type.wif), 0, xprv = master).derived
+        )
+        assertEquals(
+            "xprv9s21ZrQH143K2srSbCSg4m4kLvPMzcWydgmKEnMmoZUurYuBuYG46c6P71UGXMzmriLzCCBvKQWBUv3vPB3m1SATMhp3uEjXHJ42jFg7myX",
+            Lib().bip85(Bip85Application(Bip85Type.xprv), 0, xprv = master).derived
+        )
+        assertEquals(
+            "492db4698cf3b73a5a24998aa3e9d7fa96275d85724a91e71aa2d645442f878555d078fd1f1f67e368976f04137b1f7a0d19232136ca50c44614af72b5582a5c",
+            Lib().bip85(Bip85Application(Bip85Type.hex, num_bytes = 64), 0, xprv = master).derived
+        )
+
+        assertThrows(Exception::class.java) {
+            Lib().bip85(Bip85Application(Bip85Type.hex, num_bytes = 8), 0, xprv = master)
+        }
+    }
+
+    @Test
+    fun convert_extended_key() {
+        val mnemonic = "abandon ".repeat(11) + "about"
+        val account = Lib().derive_extended_key("m/84'/1'/0'", mnemonic = mnemonic, network = Network.testnet)
+
+        val vpub = Lib().convert_extended_key(account.xpub, KeyVersion.zpub)
+        assertTrue(vpub.key.startsWith("vpub"))
+        assertEquals(KeyVersion.xpub, vpub.original_version)
+        assertFalse(vpub.private)
+        assertEquals(account.xpub, Lib().convert_extended_key(vpub.key, KeyVersion.xpub).key)
+        assertTrue(Lib().convert_extended_key(account.xprv, KeyVersion.zpub).key.startsWith("vprv"))
+
+        val inferred = Lib().descriptor_template(
+            Network.testnet,
+            TemplateParams(xpub = vpub.key, origin = "[73c5da0a/84'/1'/0']")
+        )
+        assertTrue(inferred.descriptor.startsWith("wpkh(${account.key_origin}/0/*)#"))
+    }
+
+    @Test
+    fun multisig_descriptor() {
+        val cosigners = (1..2).map {
+            val keys = Lib().generate_extended_key(Network.testnet, 12, null)
+            Lib().derive_extended_key("m/48'/1'/0'/2'", xprv = keys.xprv).key_origin
+        }
+        val mnemonic = "abandon ".repeat(11) + "about"
+
+        val multisig = Lib().multisig_descriptor(Network.testnet, 2, MultisigScriptType.p2wsh, cosigners, mnemonic = mnemonic)
+        assertTrue(multisig.descriptor.startsWith("wsh(sortedmulti(2,"))
+        assertTrue(multisig.descriptor.endsWith("#${multisig.checksum}"))
+        assertTrue(multisig.change_descriptor.contains("/1/*"))
+        assertEquals(1, multisig.descriptor.count { it == '#' })
+        assertTrue(multisig.public_descriptor.endsWith("#${multisig.public_checksum}"))
+        assertTrue(multisig.public_change_descriptor.endsWith("#${multisig.public_change_checksum}"))
+        assertFalse(multisig.public_descriptor.contains("tprv"))
+        assertTrue(multisig.public_descriptor.contains(multisig.key_origin!!))
+        assertEquals(
+            Lib().derive_extended_key("m/48'/1'/0'/2'", mnemonic = mnemonic, network = Network.testnet).key_origin,
+            multisig.key_origin
+        )
+
+        val watchOnly = Lib().multisig_descriptor(Network.testnet, 1, MultisigScriptType.p2sh_p2wsh, cosigners)
+        assertTrue(watchOnly.descriptor.startsWith("sh(wsh(sortedmulti(1,"))
+        assertNull(watchOnly.key_origin)
+        assertEquals(watchOnly.descriptor, watchOnly.public_descriptor)
+
+        assertThrows(Exception::class.java) {
+            Lib().multisig_descriptor(Network.testnet, 4, MultisigScriptType.p2wsh, cosigners, mnemonic = mnemonic)
+        }
+    }
+
+    @Test
+    fun mnemonic_final_words() {
+        val finalWords = Lib().mnemonic_final_words("abandon ".repeat(11))
+        assertEquals(MnemonicLanguage.english, finalWords.language)
+        assertEquals(128, finalWords.words.size)
+        assertTrue(finalWords.words.contains("about"))
+        assertEquals(8, Lib().mnemonic_final_words("abandon ".repeat(23)).words.size)
+
+        assertThrows(Exception::class.java) {
+            Lib().mnemonic_final_words("abandon ".repeat(12))
+        }
+    }
+
+    @Test
+    fun restore_key_invalid() {
+        assertThrows(Exception::class.java) {
+            Lib().restore_extended_key(Network.testnet, "shell bid diarry", null)
+        }
+    }
+
     @Test
     fun restore_key() {
         val mnemonic =
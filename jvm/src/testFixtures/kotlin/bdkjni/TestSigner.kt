@@ -0,0 +1,81 @@
+package org.bitcoindevkit.bdkjni
+
+import java.math.BigInteger
+import java.security.SecureRandom
+
+/**
+ * Software [ExternalSigner] for the tests, signing with the keys derived from [xprv]
+ */
+class TestSigner(private val xprv: String) : ExternalSigner {
+
+    companion object {
+        private const val BASE58 = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz"
+        private val P = BigInteger("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEFFFFFC2F", 16)
+        private val N = BigInteger("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEBAAEDCE6AF48A03BBFD25E8CD0364141", 16)
+        private val G = Pair(
+            BigInteger("79BE667EF9DCBBAC55A06295CE870B07029BFCDB2DCE28D959F2815B16F81798", 16),
+            BigInteger("483ADA7726A3C4655DA4FBFC0E1108A8FD17B448A68554199C47D08FFB10D4B8", 16),
+        )
+
+        /** The private key of a base58check extended private key */
+        private fun privateKey(xprv: String): BigInteger {
+            var payload = BigInteger.ZERO
+            for (c in xprv) {
+                payload = payload * BigInteger.valueOf(58) + BigInteger.valueOf(BASE58.indexOf(c).toLong())
+            }
+            // 78 bytes of payload followed by the 4 bytes checksum, the key is the last 32 bytes of the payload
+            val bytes = payload.toByteArray().takeLast(82)
+            return BigInteger(1, bytes.subList(46, 78).toByteArray())
+        }
+
+        private fun add(a: Pair<BigInteger, BigInteger>?, b: Pair<BigInteger, BigInteger>?): Pair<BigInteger, BigInteger>? {
+            if (a == null) return b
+            if (b == null) return a
+            if (a.first == b.first && (a.second + b.second).mod(P) == BigInteger.ZERO) return null
+
+            val slope = if (a == b) {
+                BigInteger.valueOf(3) * a.first * a.first * (BigInteger.valueOf(2) * a.second).modInverse(P)
+            } else {
+                (b.second - a.second) * (b.first - a.first).modInverse(P)
+            }.mod(P)
+            val x = (slope * slope - a.first - b.first).mod(P)
+            return Pair(x, (slope * (a.first - x) - a.second).mod(P))
+        }
+
+        private fun multiply(k: BigInteger): Pair<BigInteger, BigInteger>? {
+            var result: Pair<BigInteger, BigInteger>? = null
+            var addend: Pair<BigInteger, BigInteger>? = G
+            for (i in 0 until k.bitLength()) {
+                if (k.testBit(i)) result = add(result, addend)
+                addend = add(addend, addend)
+            }
+            return result
+        }
+
+        /** Low-S DER-encoded ECDSA signature of [hash] with [key] */
+        private fun sign(hash: ByteArray, key: BigInteger): ByteArray {
+            val z = BigInteger(1, hash)
+            val random = SecureRandom()
+            while (true) {
+                val k = BigInteger(256, random)
+                if (k == BigInteger.ZERO || k >= N) continue
+                val r = multiply(k)!!.first.mod(N)
+                var s = (k.modInverse(N) * (z + r * key)).mod(N)
+                if (r == BigInteger.ZERO || s == BigInteger.ZERO) continue
+                if (s > N.shiftRight(1)) s = N - s
+
+                val encodedR = r.toByteArray()
+                val encodedS = s.toByteArray()
+                return byteArrayOf(0x30, (4 + encodedR.size + encodedS.size).toByte()) +
+                    byteArrayOf(0x02, encodedR.size.toByte()) + encodedR +
+                    byteArrayOf(0x02, encodedS.size.toByte()) + encodedS
+            }
+        }
+    }
+
+    override fun sign(sighash: String, fingerprint: String, path: String): String {
+        val key = privateKey(Lib().derive_extended_key(path, xprv = xprv).xprv)
+        val hash = sighash.chunked(2).map { it.toInt(16).toByte() }.toByteArray()
+        return sign(hash, key).joinToString("") { "%02x".format(it) }
+    }
+}
@@ -0,0 +1,223 @@
+//! Animated QR codes for PSBTs, using the `crypto-psbt` type of the BC-UR encoding
+//!
+//! The UR payload of a `crypto-psbt` is the PSBT wrapped in a CBOR byte string.
+
+use std::collections::BTreeSet;
+
+use crate::BdkJniError;
+
+pub(crate) const CRYPTO_PSBT: &str = "crypto-psbt";
+
+/// Wrap `data` in a CBOR byte string
+pub(crate) fn cbor_bytes(data: &[u8]) -> Vec<u8> {
+    let len = data.len();
+    let mut encoded = match len {
+        0..=23 => vec![0x40 | len as u8],
+        24..=0xff => vec![0x58, len as u8],
+        0x100..=0xffff => {
+            let mut header = vec![0x59];
+            header.extend_from_slice(&(len as u16).to_be_bytes());
+            header
+        }
+        _ => {
+            let mut header = vec![0x5a];
+            header.extend_from_slice(&(len as u32).to_be_bytes());
+            header
+        }
+    };
+    encoded.extend_from_slice(data);
+
+    encoded
+}
+
+/// Unwrap the content of a CBOR byte string
+pub(crate) fn from_cbor_bytes(data: &[u8]) -> Result<Vec<u8>, BdkJniError> {
+    let invalid = || BdkJniError::Parsing("Invalid CBOR byte string".to_string());
+
+    let (header_len, len) = match data.first().ok_or_else(invalid)? {
+        b @ 0x40..=0x57 => (1, (b & 0x1f) as usize),
+        0x58 => (2, *data.get(1).ok_or_else(invalid)? as usize),
+        0x59 => {
+            let len = data.get(1..3).ok_or_else(invalid)?;
+            (3, u16::from_be_bytes([len[0], len[1]]) as usize)
+        }
+        0x5a => {
+            let len = data.get(1..5).ok_or_else(invalid)?;
+            (
+                5,
+                u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize,
+            )
+        }
+        _ => return Err(invalid()),
+    };
+
+    data.get(header_len..header_len + len)
+        .map(|content| content.to_vec())
+        .ok_or_else(invalid)
+}
+
+/// Split a PSBT in `crypto-psbt` UR parts
+///
+/// The first `fragment_count` parts contain the PSBT as-is, the following ones are mixed by the
+/// fountain encoder so that a scanner can recover from missed frames. At least `min_parts` parts
+/// are returned.
+pub(crate) fn encode_psbt(
+    psbt: &[u8],
+    max_fragment_length: usize,
+    min_parts: usize,
+) -> Result<(Vec<String>, usize), BdkJniError> {
+    let payload = cbor_bytes(psbt);
+    let mut encoder = ur::Encoder::new(&payload, max_fragment_length, CRYPTO_PSBT)
+        .map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?;
+
+    let fragment_count = encoder.fragment_count();
+    if fragment_count == 1 {
+        return Ok((vec![ur::encode(&payload, CRYPTO_PSBT)], 1));
+    }
+
+    let parts = (0..std::cmp::max(min_parts, fragment_count))
+        .map(|_| encoder.next_part())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?;
+
+    Ok((parts, fragment_count))
+}
+
+/// Parse the `seq-len` component of a multi-part UR
+fn sequence(part: &str) -> Option<(usize, usize)> {
+    let components = part.split('/').collect::<Vec<_>>();
+    if components.len() != 3 {
+        return None;
+    }
+
+    let mut sequence = components[1].split('-');
+    let seq = sequence.next()?.parse().ok()?;
+    let len = sequence.next()?.parse().ok()?;
+
+    Some((seq, len))
+}
+
+/// Reassembles a PSBT from scanned `crypto-psbt` UR parts
+#[derive(Default)]
+pub(crate) struct UrPsbtDecoder {
+    decoder: ur::Decoder,
+    fragment_count: Option<usize>,
+    received: BTreeSet<usize>,
+    psbt: Option<Vec<u8>>,
+}
+
+impl UrPsbtDecoder {
+    /// Feed a scanned part to the decoder
+    pub(crate) fn receive(&mut self, part: &str) -> Result<(), BdkJniError> {
+        if self.psbt.is_some() {
+            return Ok(());
+        }
+
+        let part = part.trim().to_lowercase();
+        if !part.starts_with(&format!("ur:{}/", CRYPTO_PSBT)) {
+            return Err(BdkJniError::Parsing(format!(
+                "Not a `{}` UR: {}",
+                CRYPTO_PSBT, part
+            )));
+        }
+
+        match sequence(&part) {
+            // Single-part UR, the whole PSBT in one QR code
+            None => {
+                let (_, payload) =
+                    ur::decode(&part).map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?;
+                self.psbt = Some(from_cbor_bytes(&payload)?);
+            }
+            Some((seq, len)) => {
+                self.decoder
+                    .receive(&part)
+                    .map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?;
+                self.fragment_count = Some(len);
+                self.received.insert(seq);
+
+                if self.decoder.complete() {
+                    let payload = self
+                        .decoder
+                        .message()
+                        .map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?
+                        .ok_or_else(|| BdkJniError::Parsing("Incomplete UR message".to_string()))?;
+                    self.psbt = Some(from_cbor_bytes(&payload)?);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Number of fragments the PSBT has been split into, known after the first multi-part UR
+    pub(crate) fn fragment_count(&self) -> Option<usize> {
+        self.fragment_count
+    }
+
+    /// Number of distinct parts received so far
+    pub(crate) fn received(&self) -> usize {
+        self.received.len()
+    }
+
+    /// Estimated progress between 0 and 1. Mixed parts don't always carry new information, so
+    /// this only reaches 1 once the PSBT has actually been reassembled
+    pub(crate) fn progress(&self) -> f32 {
+        match (&self.psbt, self.fragment_count) {
+            (Some(_), _) => 1.0,
+            (None, Some(count)) => (self.received() as f32 / count as f32).min(0.99),
+            (None, None) => 0.0,
+        }
+    }
+
+    /// The reassembled PSBT, once all the needed parts have been received
+    pub(crate) fn psbt(&self) -> Option<&[u8]> {
+        self.psbt.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_single_part() {
+        let psbt = b"psbt\xff\x01\x00\x02".to_vec();
+        let (parts, fragment_count) = encode_psbt(&psbt, 200, 5).unwrap();
+        assert_eq!((parts.len(), fragment_count), (1, 1));
+
+        let mut decoder = UrPsbtDecoder::default();
+        decoder.receive(&parts[0].to_uppercase()).unwrap();
+        assert_eq!(decoder.psbt(), Some(&psbt[..]));
+        assert_eq!(decoder.progress(), 1.0);
+    }
+
+    #[test]
+    fn test_round_trip_multi_part() {
+        let psbt = (0..1000).map(|i| i as u8).collect::<Vec<_>>();
+        let (parts, fragment_count) = encode_psbt(&psbt, 100, 40).unwrap();
+        assert!(fragment_count > 1 && fragment_count < 40);
+        assert_eq!(parts.len(), 40);
+
+        let mut decoder = UrPsbtDecoder::default();
+        for part in &parts[..fragment_count - 1] {
+            decoder.receive(part).unwrap();
+        }
+        assert_eq!(decoder.psbt(), None);
+        assert_eq!(decoder.fragment_count(), Some(fragment_count));
+        assert!(decoder.progress() < 1.0);
+
+        // The last fragment is missed, the mixed parts have to make up for it
+        for part in &parts[fragment_count..] {
+            decoder.receive(part).unwrap();
+        }
+        assert_eq!(decoder.psbt(), Some(&psbt[..]));
+        assert_eq!(decoder.progress(), 1.0);
+    }
+
+    #[test]
+    fn test_invalid_part() {
+        let mut decoder = UrPsbtDecoder::default();
+        assert!(decoder.receive("ur:bytes/hdcxdwmdmw").is_err());
+        assert_eq!(decoder.psbt(), None);
+    }
+}
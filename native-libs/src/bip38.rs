@@ -0,0 +1,169 @@
+//! BIP38 password-protected private keys, as found on paper wallets
+
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockDecrypt, BlockEncrypt, NewBlockCipher};
+use aes::Aes256;
+use unicode_normalization::UnicodeNormalization;
+
+use bdk::bitcoin::hashes::{sha256d, Hash};
+use bdk::bitcoin::secp256k1::{PublicKey as SecpPublicKey, Secp256k1, SecretKey};
+use bdk::bitcoin::util::base58;
+use bdk::bitcoin::{Address, Network, PrivateKey};
+
+use crate::BdkJniError;
+
+/// Prefix of keys encrypted without EC multiplication
+const PREFIX_NON_EC: [u8; 2] = [0x01, 0x42];
+/// Prefix of keys generated with EC multiplication, from an intermediate code
+const PREFIX_EC: [u8; 2] = [0x01, 0x43];
+
+/// Flags always set on keys encrypted without EC multiplication
+const FLAG_NON_EC: u8 = 0xc0;
+/// Flag set when the key is used with a compressed public key
+const FLAG_COMPRESSED: u8 = 0x20;
+/// Flag set when the owner entropy contains lot and sequence numbers
+const FLAG_LOT_SEQUENCE: u8 = 0x04;
+
+fn bip38_error(msg: &str) -> BdkJniError {
+    BdkJniError::Parsing(format!("Invalid BIP38 key: {}", msg))
+}
+
+/// Whether `key` looks like a BIP38 encrypted key, they all start with `6P`
+pub(crate) fn is_encrypted(key: &str) -> bool {
+    key.trim().starts_with("6P")
+}
+
+fn scrypt(password: &[u8], salt: &[u8], log_n: u8, r: u32, p: u32, len: usize) -> Vec<u8> {
+    let params = scrypt::Params::new(log_n, r, p).expect("Valid scrypt parameters");
+    let mut output = vec![0; len];
+    scrypt::scrypt(password, salt, &params, &mut output).expect("Valid scrypt output length");
+
+    output
+}
+
+/// Password normalized as required by BIP38
+fn normalize_password(password: &str) -> Vec<u8> {
+    password.nfc().collect::<String>().into_bytes()
+}
+
+/// First 4 bytes of the double SHA256 of the P2PKH address of `key`
+fn address_hash(key: &PrivateKey) -> [u8; 4] {
+    let secp = Secp256k1::signing_only();
+    let address = Address::p2pkh(&key.public_key(&secp), key.network).to_string();
+
+    let mut hash = [0; 4];
+    hash.copy_from_slice(&sha256d::Hash::hash(address.as_bytes())[..4]);
+    hash
+}
+
+fn aes_decrypt(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let cipher = Aes256::new(GenericArray::from_slice(key));
+    let mut block = GenericArray::clone_from_slice(data);
+    cipher.decrypt_block(&mut block);
+
+    block.to_vec()
+}
+
+fn aes_encrypt(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let cipher = Aes256::new(GenericArray::from_slice(key));
+    let mut block = GenericArray::clone_from_slice(data);
+    cipher.encrypt_block(&mut block);
+
+    block.to_vec()
+}
+
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    a.iter().zip(b).map(|(a, b)| a ^ b).collect()
+}
+
+/// Encrypt a private key with `password`, without EC multiplication
+pub(crate) fn encrypt(key: &PrivateKey, password: &str) -> String {
+    let address_hash = address_hash(key);
+    let derived = scrypt(&normalize_password(password), &address_hash, 14, 8, 8, 64);
+    let (half1, half2) = derived.split_at(32);
+    let secret = &key.key[..];
+
+    let flag = if key.compressed {
+        FLAG_NON_EC | FLAG_COMPRESSED
+    } else {
+        FLAG_NON_EC
+    };
+    let mut data = PREFIX_NON_EC.to_vec();
+    data.push(flag);
+    data.extend_from_slice(&address_hash);
+    data.extend(aes_encrypt(half2, &xor(&secret[..16], &half1[..16])));
+    data.extend(aes_encrypt(half2, &xor(&secret[16..], &half1[16..])));
+
+    base58::check_encode_slice(&data)
+}
+
+/// Decrypt a BIP38 key, the resulting private key is checked against the address hash so a
+/// wrong password is reported as an error
+///
+/// BIP38 only defines mainnet keys, keys whose address hash matches a testnet address are
+/// accepted too and returned as testnet keys.
+pub(crate) fn decrypt(key: &str, password: &str) -> Result<PrivateKey, BdkJniError> {
+    let data = base58::from_check(key.trim()).map_err(|e| bip38_error(&e.to_string()))?;
+    if data.len() != 39 {
+        return Err(bip38_error("wrong length"));
+    }
+
+    let password = normalize_password(password);
+    let flag = data[2];
+    let compressed = flag & FLAG_COMPRESSED != 0;
+    let address_hash_bytes = &data[3..7];
+
+    let secret = match [data[0], data[1]] {
+        PREFIX_NON_EC => {
+            let derived = scrypt(&password, address_hash_bytes, 14, 8, 8, 64);
+            let (half1, half2) = derived.split_at(32);
+
+            let mut secret = xor(&aes_decrypt(half2, &data[7..23]), &half1[..16]);
+            secret.extend(xor(&aes_decrypt(half2, &data[23..39]), &half1[16..]));
+            SecretKey::from_slice(&secret).map_err(|e| bip38_error(&e.to_string()))?
+        }
+        PREFIX_EC => {
+            let owner_entropy = &data[7..15];
+            let owner_salt = match flag & FLAG_LOT_SEQUENCE {
+                0 => owner_entropy,
+                _ => &owner_entropy[..4],
+            };
+
+            let prefactor = scrypt(&password, owner_salt, 14, 8, 8, 32);
+            let passfactor = match flag & FLAG_LOT_SEQUENCE {
+                0 => prefactor,
+                _ => sha256d::Hash::hash(&[&prefactor[..], owner_entropy].concat()).to_vec(),
+            };
+            let passfactor =
+                SecretKey::from_slice(&passfactor).map_err(|e| bip38_error(&e.to_string()))?;
+            let passpoint = SecpPublicKey::from_secret_key(&Secp256k1::new(), &passfactor);
+
+            let salt = [address_hash_bytes, owner_entropy].concat();
+            let derived = scrypt(&passpoint.serialize(), &salt, 10, 1, 1, 64);
+            let (half1, half2) = derived.split_at(32);
+
+            let decrypted2 = xor(&aes_decrypt(half2, &data[23..39]), &half1[16..]);
+            let encrypted1 = [&data[15..23], &decrypted2[..8]].concat();
+            let mut seedb = xor(&aes_decrypt(half2, &encrypted1), &half1[..16]);
+            seedb.extend_from_slice(&decrypted2[8..]);
+
+            let factorb = sha256d::Hash::hash(&seedb);
+            let mut secret = passfactor;
+            secret
+                .mul_assign(&factorb[..])
+                .map_err(|e| bip38_error(&e.to_string()))?;
+            secret
+        }
+        _ => return Err(bip38_error("unknown prefix")),
+    };
+
+    [Network::Bitcoin, Network::Testnet]
+        .iter()
+        .map(|network| PrivateKey {
+            compressed,
+            network: *network,
+            key: secret,
+        })
+        .find(|private_key| address_hash(private_key) == address_hash_bytes)
+        .ok_or(BdkJniError::WrongPassword)
+}
@@ -0,0 +1,200 @@
+//! BIP85 deterministic entropy, to derive child seeds and keys from a single master key
+
+use std::convert::TryFrom;
+
+use serde::Deserialize;
+
+use bdk::bitcoin::hashes::hex::ToHex;
+use bdk::bitcoin::hashes::{hmac, sha512, Hash, HashEngine};
+use bdk::bitcoin::secp256k1::Secp256k1;
+use bdk::bitcoin::util::bip32::{ChainCode, ChildNumber, DerivationPath, ExtendedPrivKey};
+use bdk::bitcoin::PrivateKey;
+use bdk::keys::bip39::Mnemonic;
+
+use crate::mnemonic::{self, WordlistLanguage};
+use crate::BdkJniError;
+
+/// First hardened step of every BIP85 path
+const BIP85_PURPOSE: u32 = 83696968;
+
+/// The BIP85 applications, each one deriving a different kind of child secret
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum Application {
+    /// A mnemonic of `word_count` words
+    Bip39 {
+        word_count: usize,
+        language: Option<WordlistLanguage>,
+    },
+    /// `num_bytes` bytes of raw entropy, between 16 and 64
+    Hex { num_bytes: u32 },
+    /// A master xprv
+    Xprv,
+    /// A compressed private key in WIF
+    Wif,
+}
+
+/// Language index used in the derivation path of the BIP39 application
+fn language_index(language: WordlistLanguage) -> u32 {
+    match language {
+        WordlistLanguage::English => 0,
+        WordlistLanguage::Japanese => 1,
+        WordlistLanguage::Korean => 2,
+        WordlistLanguage::Spanish => 3,
+        WordlistLanguage::ChineseSimplified => 4,
+        WordlistLanguage::ChineseTraditional => 5,
+        WordlistLanguage::French => 6,
+        WordlistLanguage::Italian => 7,
+    }
+}
+
+impl Application {
+    /// The hardened path steps after the BIP85 purpose, `index` excluded
+    fn path(self) -> Result<Vec<u32>, BdkJniError> {
+        Ok(match self {
+            Application::Bip39 {
+                word_count,
+                language,
+            } => vec![
+                39,
+                language_index(language.unwrap_or_default()),
+                u32::try_from(word_count).map_err(|_| {
+                    BdkJniError::Parsing(format!("Invalid number of words {}", word_count))
+                })?,
+            ],
+            Application::Hex { num_bytes } => vec![128169, num_bytes],
+            Application::Xprv => vec![32],
+            Application::Wif => vec![2],
+        })
+    }
+}
+
+/// The derivation path of `application` at `index`
+///
+/// Fails when a step doesn't fit in a hardened child number, instead of wrapping around.
+pub(crate) fn derivation_path(
+    application: Application,
+    index: u32,
+) -> Result<DerivationPath, BdkJniError> {
+    std::iter::once(BIP85_PURPOSE)
+        .chain(application.path()?)
+        .chain(std::iter::once(index))
+        .map(|index| {
+            ChildNumber::from_hardened_idx(index).map_err(|_| {
+                BdkJniError::Parsing(format!(
+                    "Invalid path step {}, hardened indexes must be below 2^31",
+                    index
+                ))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(DerivationPath::from)
+}
+
+/// The 64 bytes of entropy derived from `master` at `path`
+fn entropy(master: &ExtendedPrivKey, path: &DerivationPath) -> Result<Vec<u8>, BdkJniError> {
+    let secp = Secp256k1::new();
+    let derived = master.derive_priv(&secp, path)?;
+
+    let mut engine = hmac::HmacEngine::<sha512::Hash>::new(b"bip-entropy-from-k");
+    engine.input(&derived.private_key.to_bytes());
+
+    Ok(hmac::Hmac::<sha512::Hash>::from_engine(engine)[..].to_vec())
+}
+
+/// Derive the child secret of `application` at `index`, returned in its usual string encoding
+pub(crate) fn derive(
+    master: &ExtendedPrivKey,
+    application: Application,
+    index: u32,
+) -> Result<String, BdkJniError> {
+    let path = derivation_path(application, index)?;
+    let entropy = entropy(master, &path)?;
+
+    match application {
+        Application::Bip39 {
+            word_count,
+            language,
+        } => {
+            let len = mnemonic::mnemonic_type(word_count)?.entropy_bits() / 8;
+            let mnemonic =
+                Mnemonic::from_entropy(&entropy[..len], language.unwrap_or_default().into())
+                    .map_err(|e| BdkJniError::Mnemonic(e.to_string()))?;
+
+            Ok(mnemonic.to_string())
+        }
+        Application::Hex { num_bytes } => {
+            if !(16..=64).contains(&num_bytes) {
+                return Err(BdkJniError::Parsing(format!(
+                    "Invalid number of bytes {}, must be between 16 and 64",
+                    num_bytes
+                )));
+            }
+
+            Ok(entropy[..num_bytes as usize].to_hex())
+        }
+        Application::Xprv => {
+            let xprv = ExtendedPrivKey {
+                network: master.network,
+                depth: 0,
+                parent_fingerprint: Default::default(),
+                child_number: ChildNumber::Normal { index: 0 },
+                private_key: private_key(&entropy[32..], master)?,
+                chain_code: ChainCode::from(&entropy[..32]),
+            };
+
+            Ok(xprv.to_string())
+        }
+        Application::Wif => Ok(private_key(&entropy[..32], master)?.to_wif()),
+    }
+}
+
+fn private_key(data: &[u8], master: &ExtendedPrivKey) -> Result<PrivateKey, BdkJniError> {
+    PrivateKey::from_slice(data, master.network).map_err(|e| BdkJniError::Parsing(e.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_derivation_path() {
+        let path = derivation_path(
+            Application::Bip39 {
+                word_count: 12,
+                language: None,
+            },
+            0,
+        )
+        .unwrap();
+        assert_eq!(path.to_string(), "m/83696968'/39'/0'/12'/0'");
+        assert_eq!(
+            derivation_path(Application::Hex { num_bytes: 64 }, 3)
+                .unwrap()
+                .to_string(),
+            "m/83696968'/128169'/64'/3'"
+        );
+    }
+
+    #[test]
+    fn test_derivation_path_out_of_range() {
+        assert!(matches!(
+            derivation_path(Application::Wif, 1 << 31),
+            Err(BdkJniError::Parsing(_))
+        ));
+        assert!(matches!(
+            derivation_path(Application::Hex { num_bytes: 1 << 31 }, 0),
+            Err(BdkJniError::Parsing(_))
+        ));
+        assert!(matches!(
+            derivation_path(
+                Application::Bip39 {
+                    word_count: 1 << 31,
+                    language: None,
+                },
+                0
+            ),
+            Err(BdkJniError::Parsing(_))
+        ));
+    }
+}
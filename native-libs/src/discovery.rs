@@ -0,0 +1,77 @@
+//! Discovery of the accounts used by a master key, following the BIP44 account structure
+
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use bdk::bitcoin::util::bip32::ExtendedPrivKey;
+use bdk::blockchain::{
+    noop_progress, ConfigurableBlockchain, ElectrumBlockchain, ElectrumBlockchainConfig,
+};
+use bdk::database::MemoryDatabase;
+use bdk::Wallet;
+
+use crate::templates::{self, Template};
+use crate::{format_key_origin, BdkJniError};
+
+#[derive(Debug, Serialize)]
+pub(crate) struct UsedAccount {
+    pub(crate) template: Template,
+    pub(crate) account: u32,
+    /// Public descriptors, with the origin of the account xpub
+    pub(crate) descriptor: String,
+    pub(crate) change_descriptor: String,
+    pub(crate) xpub: String,
+    /// Origin of `xpub`, as `[fingerprint/path]`
+    pub(crate) origin: String,
+    pub(crate) balance: u64,
+    pub(crate) transactions: usize,
+}
+
+/// Sync accounts `0..accounts` of each template in a temporary in-memory watch-only wallet and
+/// return the ones with any transaction
+///
+/// Addresses are scanned up to the stop gap of `config`, and every account is checked even after
+/// an unused one since users don't always create accounts in order. A single Electrum client is
+/// shared by all the wallets.
+pub(crate) fn discover(
+    master: &ExtendedPrivKey,
+    templates: &[Template],
+    accounts: u32,
+    config: &ElectrumBlockchainConfig,
+) -> Result<Vec<UsedAccount>, BdkJniError> {
+    let client = Arc::new(ElectrumBlockchain::from_config(config)?);
+    let mut used = Vec::new();
+
+    for template in templates {
+        for account in 0..accounts {
+            let (descriptor, change_descriptor, xpub, (fingerprint, path)) =
+                templates::master_public_descriptors(*template, master, account)?;
+
+            let wallet = Wallet::new(
+                descriptor.as_str(),
+                Some(change_descriptor.as_str()),
+                master.network,
+                MemoryDatabase::new(),
+                Arc::clone(&client),
+            )?;
+            wallet.sync(noop_progress(), None)?;
+
+            let transactions = wallet.list_transactions(false)?.len();
+            if transactions > 0 {
+                used.push(UsedAccount {
+                    template: *template,
+                    account,
+                    descriptor,
+                    change_descriptor,
+                    xpub: xpub.to_string(),
+                    origin: format_key_origin(fingerprint, &path),
+                    balance: wallet.get_balance()?,
+                    transactions,
+                });
+            }
+        }
+    }
+
+    Ok(used)
+}
@@ -8,6 +8,7 @@ use std::collections::BTreeMap;
 use std::hash::{Hash, Hasher};
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::{Mutex, PoisonError};
 
 use serde::ser::{SerializeStruct, Serializer};
 use serde::{Deserialize, Serialize};
@@ -19,20 +20,52 @@ use bdk::sled;
 use bdk::Wallet;
 use bdk::{bitcoin, KeychainKind, SignOptions};
 
-use bdk::bitcoin::secp256k1::Secp256k1;
+use bdk::bitcoin::secp256k1::{All, Secp256k1};
 use bdk::blockchain::{
     noop_progress, ConfigurableBlockchain, ElectrumBlockchain, ElectrumBlockchainConfig,
 };
+use bdk::descriptor::get_checksum;
 use bdk::{FeeRate, TransactionDetails};
 
-use bdk::keys::bip39::{Language, Mnemonic, MnemonicType};
+use bdk::keys::bip39::{Language, Mnemonic};
 use bdk::keys::{DerivableKey, ExtendedKey, GeneratableKey, GeneratedKey};
 use bdk::miniscript::miniscript;
+use bdk::signer::{Signer, SignerError};
 use bdk::wallet::AddressIndex::{LastUnused, New};
 use bitcoin::consensus::encode::{deserialize, serialize};
 use bitcoin::hashes::hex::{FromHex, ToHex};
+use bitcoin::hashes::Hash;
+use bitcoin::util::bip143::SigHashCache;
+use bitcoin::util::bip32::{
+    self, DerivationPath, ExtendedPrivKey, ExtendedPubKey, Fingerprint, KeySource,
+};
 use bitcoin::util::psbt::PartiallySignedTransaction;
-use bitcoin::{Address, Network, OutPoint, Transaction};
+use bitcoin::{
+    Address, Network, OutPoint, PrivateKey, PubkeyHash, PublicKey, Script, SigHash, SigHashType,
+    Transaction, TxOut,
+};
+
+mod bcur;
+mod bip38;
+mod bip85;
+mod discovery;
+mod message;
+mod mnemonic;
+mod multisig;
+mod psbt_v2;
+mod reserves;
+mod slip132;
+mod slip39;
+mod sweep;
+mod templates;
+
+use bcur::UrPsbtDecoder;
+use message::MessageFormat;
+use mnemonic::{EntropySource, WordlistLanguage};
+use multisig::MultisigScriptType;
+use slip132::KeyVersion;
+use slip39::Slip39Group;
+use templates::{Template, TemplateParams};
 
 #[derive(Debug, Deserialize)]
 struct KotlinPair<F: std::fmt::Debug, S: std::fmt::Debug> {
@@ -48,6 +81,29 @@ impl<F: std::fmt::Debug, S: std::fmt::Debug> From<KotlinPair<F, S>> for (F, S) {
     }
 }
 
+/// A PSBT sent by the JVM, either as a base64 or hex string or as raw bytes
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum PsbtData {
+    Bytes(Vec<u8>),
+    Text(String),
+}
+
+/// Encoding of the PSBTs returned to the JVM
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum PsbtFormat {
+    Base64,
+    Hex,
+    Binary,
+}
+
+impl Default for PsbtFormat {
+    fn default() -> Self {
+        PsbtFormat::Base64
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(tag = "method", content = "params")]
 #[serde(rename_all = "snake_case")]
@@ -56,8 +112,10 @@ enum BdkRequest {
         name: String,
         network: Network,
         path: PathBuf,
-        descriptor: String,
+        descriptor: Option<String>,
         change_descriptor: Option<String>,
+        /// Build the descriptors from a template instead of `descriptor` and `change_descriptor`
+        template: Option<TemplateParams>,
 
         electrum_url: String,
         electrum_proxy: Option<String>,
@@ -101,18 +159,33 @@ enum BdkRequest {
         utxos: Option<Vec<String>>,
         send_all: Option<bool>,
         policy: Option<BTreeMap<String, Vec<usize>>>,
+
+        psbt_format: Option<PsbtFormat>,
+
+        psbt_version: Option<u32>,
     },
     Sign {
         wallet: IntermediatePtr,
 
-        psbt: String,
+        psbt: PsbtData,
+        psbt_format: Option<PsbtFormat>,
+        /// Version of the returned PSBT, the one of `psbt` by default
+        psbt_version: Option<u32>,
 
         assume_height: Option<u32>,
+        trust_witness_utxo: Option<bool>,
+        allow_all_sighashes: Option<bool>,
+        /// Remove the partial signatures of finalized inputs, `false` by default like bdk
+        remove_partial_sigs: Option<bool>,
+        try_finalize: Option<bool>,
+        /// Sighash type (eg. `SIGHASH_ALL|SIGHASH_ANYONECANPAY`) to use for inputs that don't
+        /// specify one
+        sighash_type: Option<String>,
     },
     ExtractPsbt {
         wallet: IntermediatePtr,
 
-        psbt: String,
+        psbt: PsbtData,
     },
     Broadcast {
         wallet: IntermediatePtr,
@@ -122,17 +195,261 @@ enum BdkRequest {
     PublicDescriptors {
         wallet: IntermediatePtr,
     },
+    /// Build a signed transaction moving all the funds of a WIF or BIP38 key to the wallet
+    SweepPrivateKey {
+        wallet: IntermediatePtr,
+
+        key: String,
+        /// Required for BIP38 keys
+        password: Option<String>,
+        fee_rate: f32,
+    },
+    /// Sign a message with the key behind one of the wallet's addresses
+    SignMessage {
+        wallet: IntermediatePtr,
+
+        address: String,
+        message: String,
+        /// BIP137 by default
+        format: Option<MessageFormat>,
+    },
+    /// Build an unsigned BIP127 proof of reserves for a challenge message, to be signed with `Sign`
+    CreateReserveProof {
+        wallet: IntermediatePtr,
+
+        message: String,
+        /// Only prove these UTXOs instead of all of them
+        utxos: Option<Vec<String>>,
+
+        psbt_format: Option<PsbtFormat>,
+        psbt_version: Option<u32>,
+    },
+    /// Verify a finalized proof of reserves against the current UTXOs of the wallet
+    VerifyReserveProof {
+        wallet: IntermediatePtr,
+
+        psbt: PsbtData,
+        message: String,
+    },
+    /// Register a signer backed by a JVM callback, only accepted by `call_with_signer`
+    AddExternalSigner {
+        wallet: IntermediatePtr,
+
+        /// Fingerprint of the master key held by the external signer
+        fingerprint: String,
+        keychain: Option<KeychainKind>,
+        ordering: Option<usize>,
+    },
+    /// Sign a PSBT with an xprv or WIF key, without a wallet
+    SignPsbtWithKey {
+        psbt: PsbtData,
+        psbt_format: Option<PsbtFormat>,
+        /// Version of the returned PSBT, the one of `psbt` by default
+        psbt_version: Option<u32>,
+        /// Extended private key or WIF
+        key: String,
+        /// Origin of `key` in the `[fingerprint/path]` format, if it's not a master key
+        origin: Option<String>,
+        /// Sign inputs asking for a sighash other than `SIGHASH_ALL`, `false` by default
+        allow_all_sighashes: Option<bool>,
+    },
+    /// Convert a PSBT between versions 0 and 2
+    ConvertPsbt {
+        psbt: PsbtData,
+        psbt_format: Option<PsbtFormat>,
+        psbt_version: u32,
+    },
+    /// Merge the signatures and metadata of PSBTs for the same transaction
+    CombinePsbts {
+        psbts: Vec<PsbtData>,
+        psbt_format: Option<PsbtFormat>,
+        /// Version of the returned PSBT, the one of the first PSBT by default
+        psbt_version: Option<u32>,
+    },
+    /// Split a PSBT in animated QR code frames, encoded as `ur:crypto-psbt` parts
+    EncodeUrPsbt {
+        psbt: PsbtData,
+        /// Maximum length of the fragments, 200 by default
+        max_fragment_length: Option<usize>,
+        /// Minimum number of parts to generate, including the extra fountain-coded parts
+        min_parts: Option<usize>,
+    },
+    /// Start collecting scanned `ur:crypto-psbt` parts
+    NewUrDecoder {},
+    ReceiveUrPart {
+        decoder: IntermediatePtr,
+
+        part: String,
+        psbt_format: Option<PsbtFormat>,
+        /// Version of the returned PSBT, the one of the scanned PSBT by default
+        psbt_version: Option<u32>,
+    },
+    FreeUrDecoder {
+        decoder: IntermediatePtr,
+    },
     /// Generate new random seed mnemonic phrase and corresponding master extended key
     GenerateExtendedKey {
         network: Network,
+        /// One of 12, 15, 18, 21 or 24
         word_count: usize,
         password: Option<String>,
+        language: Option<WordlistLanguage>,
+        /// Entropy to use instead of the OS random number generator
+        entropy: Option<String>,
+        /// How `entropy` is encoded, `hex` by default
+        entropy_source: Option<EntropySource>,
+        /// XOR `entropy` with the OS random number generator
+        mix_system_entropy: Option<bool>,
     },
     /// Restore a master extended key from seed backup mnemonic words
     RestoreExtendedKey {
         network: Network,
         mnemonic: String,
         password: Option<String>,
+        language: Option<WordlistLanguage>,
+    },
+    /// Check a mnemonic phrase without failing, reporting the invalid words and checksum
+    ValidateMnemonic {
+        mnemonic: String,
+        /// Detected from the words when missing
+        language: Option<WordlistLanguage>,
+    },
+    /// External and internal descriptors of a BIP44, BIP49 or BIP84 wallet
+    DescriptorTemplate {
+        network: Network,
+        template: TemplateParams,
+    },
+    /// External and internal descriptors of a sorted multisig
+    MultisigDescriptor {
+        network: Network,
+        threshold: usize,
+        /// Inferred from the version of Ypub or Zpub cosigner keys when missing
+        script_type: Option<MultisigScriptType>,
+        /// Account-level xpubs of the other cosigners, optionally prefixed by their origin
+        cosigners: Vec<String>,
+
+        /// Our own master key, derived at its BIP48 path
+        xprv: Option<String>,
+        mnemonic: Option<String>,
+        password: Option<String>,
+        language: Option<WordlistLanguage>,
+        account: Option<u32>,
+    },
+    /// Derive a child mnemonic, hex entropy, xprv or WIF from a master key with BIP85
+    Bip85 {
+        xprv: Option<String>,
+        mnemonic: Option<String>,
+        password: Option<String>,
+        language: Option<WordlistLanguage>,
+        /// Required with `mnemonic`
+        network: Option<Network>,
+
+        application: bip85::Application,
+        index: u32,
+    },
+    /// Split a master secret, or the entropy of a BIP39 mnemonic, in SLIP-39 shares
+    ///
+    /// The shares of a mnemonic hold its entropy, not its BIP39 seed: wallets implementing SLIP-39
+    /// (e.g. Trezor) derive their keys from the master secret directly, so they restore a
+    /// different wallet. The original wallet is only restored by converting the recovered master
+    /// secret back to the BIP39 mnemonic with `Slip39Combine`.
+    Slip39Split {
+        /// Hex encoded, between 16 and 32 bytes
+        master_secret: Option<String>,
+        mnemonic: Option<String>,
+        language: Option<WordlistLanguage>,
+
+        group_threshold: u8,
+        groups: Vec<Slip39Group>,
+        passphrase: Option<String>,
+        iteration_exponent: Option<u8>,
+    },
+    /// Recover a master secret from SLIP-39 shares
+    Slip39Combine {
+        shares: Vec<String>,
+        passphrase: Option<String>,
+        /// Language of the BIP39 mnemonic encoding the master secret
+        language: Option<WordlistLanguage>,
+    },
+    /// Split a mnemonic in Seed XOR parts, each one a valid mnemonic of the same length
+    SeedXorSplit {
+        mnemonic: String,
+        language: Option<WordlistLanguage>,
+        parts: usize,
+    },
+    /// Recombine the parts of a Seed XOR backup
+    SeedXorCombine {
+        parts: Vec<String>,
+        language: Option<WordlistLanguage>,
+    },
+    /// Master fingerprint and first addresses of a mnemonic and passphrase, to check that the
+    /// passphrase has been typed correctly
+    VerifyPassphrase {
+        network: Network,
+        mnemonic: String,
+        password: Option<String>,
+        language: Option<WordlistLanguage>,
+        /// Fingerprint stored when the wallet was first created
+        expected_fingerprint: Option<String>,
+    },
+    /// Encrypt a WIF private key with BIP38
+    Bip38Encrypt {
+        wif: String,
+        password: String,
+    },
+    /// Decrypt a BIP38 private key to WIF
+    Bip38Decrypt {
+        key: String,
+        password: String,
+    },
+    /// Verify a BIP137 or BIP322 message signature, detecting its format
+    VerifyMessage {
+        address: String,
+        message: String,
+        /// Base64 encoded
+        signature: String,
+    },
+    /// Find the BIP44, BIP49 and BIP84 accounts of a master key with any history
+    DiscoverAccounts {
+        network: Network,
+        xprv: Option<String>,
+        mnemonic: Option<String>,
+        password: Option<String>,
+        language: Option<WordlistLanguage>,
+
+        /// All of them when missing
+        templates: Option<Vec<Template>>,
+        /// Number of accounts checked for each template, 5 by default
+        accounts: Option<u32>,
+
+        electrum_url: String,
+        electrum_proxy: Option<String>,
+        electrum_retry: u8,
+        electrum_timeout: Option<u8>,
+        electrum_stop_gap: usize,
+    },
+    /// Convert an extended key between xpub/tpub and its SLIP-132 variants
+    ConvertExtendedKey {
+        key: String,
+        version: KeyVersion,
+    },
+    /// Derive a child key of a master xprv or of the master key of a mnemonic
+    DeriveExtendedKey {
+        /// Master key, keys of depth other than 0 are rejected since the key origin is relative to it
+        xprv: Option<String>,
+        mnemonic: Option<String>,
+        password: Option<String>,
+        language: Option<WordlistLanguage>,
+        /// Required with `mnemonic`
+        network: Option<Network>,
+        path: String,
+    },
+    /// List the final words giving a valid checksum to a mnemonic missing its last word
+    MnemonicFinalWords {
+        /// 11, 14, 17, 20 or 23 words
+        mnemonic: String,
+        /// Detected from the words when missing
+        language: Option<WordlistLanguage>,
     },
 }
 
@@ -149,6 +466,21 @@ enum BdkJniError {
     Parsing(String),
 
     ExtKeyError(bdk::keys::KeyError),
+    Mnemonic(String),
+    Bip32(bip32::Error),
+    SignerError(SignerError),
+
+    /// The password of an encrypted key is wrong
+    WrongPassword,
+
+    /// The PSBT still has inputs without a final scriptSig or witness, listed by index
+    PsbtNotFinalized(Vec<usize>),
+
+    /// An `ExternalSigner` threw, with the description of the Java exception
+    ExternalSigner(String),
+
+    /// The proof of reserves doesn't prove anything, with the reason
+    InvalidReserveProof(String),
 }
 
 impl From<bdk::Error> for BdkJniError {
@@ -172,6 +504,24 @@ impl From<bdk::keys::KeyError> for BdkJniError {
     }
 }
 
+impl From<bip32::Error> for BdkJniError {
+    fn from(other: bip32::Error) -> Self {
+        BdkJniError::Bip32(other)
+    }
+}
+
+impl From<SignerError> for BdkJniError {
+    fn from(other: SignerError) -> Self {
+        BdkJniError::SignerError(other)
+    }
+}
+
+/// Wallets handed to the JVM, which can call into them from any thread
+///
+/// bdk wallets aren't `Sync`, their database lives in a `RefCell`, so every call takes the lock
+/// exclusively. A signer calling back into the same wallet while signing deadlocks.
+type WalletLock = Mutex<Wallet<ElectrumBlockchain, sled::Tree>>;
+
 #[derive(Debug, Clone)]
 struct OpaquePtr<T> {
     raw: *const T,
@@ -235,6 +585,362 @@ struct IntermediatePtr {
     id: [u8; 8],
 }
 
+/// Magic bytes at the beginning of every serialized PSBT
+const PSBT_MAGIC: &[u8] = b"psbt\xff";
+
+/// Decode a PSBT, detecting whether it's been sent as raw bytes, hex or base64. Version 2 PSBTs
+/// are converted to version 0, the original version is returned with the PSBT
+fn decode_psbt(data: &PsbtData) -> Result<(PartiallySignedTransaction, u32), BdkJniError> {
+    let bytes = match data {
+        PsbtData::Bytes(bytes) => bytes.clone(),
+        PsbtData::Text(text) => {
+            let text = text.trim();
+            match Vec::<u8>::from_hex(text) {
+                Ok(bytes) if bytes.starts_with(PSBT_MAGIC) => bytes,
+                _ => base64::decode(text).map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?,
+            }
+        }
+    };
+
+    let (bytes, version) = psbt_v2::to_v0(bytes)?;
+    let psbt = deserialize(&bytes).map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?;
+
+    Ok((psbt, version))
+}
+
+/// PSBT returned to the JVM, flattened in the responses
+#[derive(Debug, Serialize)]
+struct EncodedPsbt {
+    /// Hex if requested, base64 otherwise
+    psbt: String,
+    /// Raw bytes, only for `PsbtFormat::Binary`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    psbt_bytes: Option<Vec<u8>>,
+}
+
+/// Encode a PSBT in the format and version requested by the JVM
+fn encode_psbt(
+    psbt: &PartiallySignedTransaction,
+    format: Option<PsbtFormat>,
+    version: Option<u32>,
+) -> Result<EncodedPsbt, BdkJniError> {
+    let bytes = psbt_v2::from_v0(serialize(psbt), version.unwrap_or(0))?;
+    Ok(match format.unwrap_or_default() {
+        PsbtFormat::Base64 => EncodedPsbt {
+            psbt: base64::encode(&bytes),
+            psbt_bytes: None,
+        },
+        PsbtFormat::Hex => EncodedPsbt {
+            psbt: bytes.to_hex(),
+            psbt_bytes: None,
+        },
+        PsbtFormat::Binary => EncodedPsbt {
+            psbt: base64::encode(&bytes),
+            psbt_bytes: Some(bytes),
+        },
+    })
+}
+
+/// Return the output spent by a PSBT input, if the PSBT carries it
+fn spent_txout(psbt: &PartiallySignedTransaction, index: usize) -> Option<TxOut> {
+    let input = psbt.inputs.get(index)?;
+    match (&input.witness_utxo, &input.non_witness_utxo) {
+        (Some(txout), _) => Some(txout.clone()),
+        (None, Some(prev_tx)) => {
+            let vout = psbt
+                .global
+                .unsigned_tx
+                .input
+                .get(index)?
+                .previous_output
+                .vout;
+            prev_tx.output.get(vout as usize).cloned()
+        }
+        (None, None) => None,
+    }
+}
+
+/// Compute the sighash that has to be signed for a PSBT input, following the same rules as bdk's
+/// signers: inputs with a `witness_utxo` are signed as segwit v0, the others as legacy
+fn input_sighash(
+    psbt: &PartiallySignedTransaction,
+    index: usize,
+) -> Result<(SigHash, SigHashType), SignerError> {
+    let input = psbt
+        .inputs
+        .get(index)
+        .ok_or(SignerError::InputIndexOutOfRange)?;
+    let tx = &psbt.global.unsigned_tx;
+    let sighash_type = input.sighash_type.unwrap_or(SigHashType::All);
+
+    match &input.witness_utxo {
+        Some(witness_utxo) => {
+            let script_code = match &input.witness_script {
+                Some(witness_script) => witness_script.clone(),
+                None => {
+                    let script = input
+                        .redeem_script
+                        .as_ref()
+                        .unwrap_or(&witness_utxo.script_pubkey);
+                    if !script.is_v0_p2wpkh() {
+                        return Err(SignerError::MissingWitnessScript);
+                    }
+                    let pubkey_hash = PubkeyHash::from_slice(&script.as_bytes()[2..])
+                        .map_err(|_| SignerError::MissingWitnessScript)?;
+                    Script::new_p2pkh(&pubkey_hash)
+                }
+            };
+
+            let sighash = SigHashCache::new(tx).signature_hash(
+                index,
+                &script_code,
+                witness_utxo.value,
+                sighash_type,
+            );
+            Ok((sighash, sighash_type))
+        }
+        None => {
+            let prev_tx = input
+                .non_witness_utxo
+                .as_ref()
+                .ok_or(SignerError::MissingNonWitnessUtxo)?;
+            let vout = tx.input[index].previous_output.vout as usize;
+            let script = match &input.redeem_script {
+                Some(redeem_script) => redeem_script.clone(),
+                None => prev_tx
+                    .output
+                    .get(vout)
+                    .ok_or(SignerError::InvalidNonWitnessUtxo)?
+                    .script_pubkey
+                    .clone(),
+            };
+
+            let sighash = tx.signature_hash(index, &script, sighash_type.as_u32());
+            Ok((sighash, sighash_type))
+        }
+    }
+}
+
+/// Compute the fee paid by a PSBT, if the previous outputs of all its inputs are known
+fn psbt_fee(psbt: &PartiallySignedTransaction) -> Option<u64> {
+    let input_value = (0..psbt.inputs.len())
+        .map(|index| spent_txout(psbt, index).map(|txout| txout.value))
+        .sum::<Option<u64>>()?;
+    let output_value = psbt
+        .global
+        .unsigned_tx
+        .output
+        .iter()
+        .map(|txout| txout.value)
+        .sum::<u64>();
+
+    input_value.checked_sub(output_value)
+}
+
+/// Parse a key origin in the `[fingerprint/path]` format used in descriptors
+fn parse_key_origin(origin: &str) -> Result<KeySource, BdkJniError> {
+    let origin = origin.trim_start_matches('[').trim_end_matches(']');
+    let (fingerprint, path) = match origin.find('/') {
+        Some(pos) => (&origin[..pos], &origin[pos..]),
+        None => (origin, ""),
+    };
+
+    let fingerprint =
+        Fingerprint::from_str(fingerprint).map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?;
+    let path = DerivationPath::from_str(&format!("m{}", path))
+        .map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?;
+
+    Ok((fingerprint, path))
+}
+
+/// Format a key origin as `[fingerprint/path]`, the way it's written in descriptors
+fn format_key_origin(fingerprint: Fingerprint, path: &DerivationPath) -> String {
+    format!(
+        "[{}{}]",
+        fingerprint,
+        path.to_string().trim_start_matches('m')
+    )
+}
+
+/// A descriptor string with its checksum, and the checksum alone
+///
+/// Descriptors from miniscript are already formatted with a checksum, it's recomputed to also
+/// accept strings without one.
+fn descriptor_with_checksum(descriptor: &str) -> Result<(String, String), BdkJniError> {
+    let descriptor = descriptor.split('#').next().unwrap_or_default();
+    let checksum = get_checksum(descriptor).map_err(bdk::Error::from)?;
+
+    Ok((format!("{}#{}", descriptor, checksum), checksum))
+}
+
+/// Check that input `index` of the serialized transaction `tx` satisfies `script_pubkey` with
+/// libbitcoinconsensus
+#[cfg(not(target_os = "android"))]
+fn verify_script(
+    script_pubkey: &Script,
+    value: u64,
+    tx: &[u8],
+    index: usize,
+) -> Result<bool, BdkJniError> {
+    Ok(bitcoinconsensus::verify(script_pubkey.as_bytes(), value, tx, index).is_ok())
+}
+
+/// libbitcoinconsensus is a C++ library, it's not part of the Android build
+#[cfg(target_os = "android")]
+fn verify_script(
+    _script_pubkey: &Script,
+    _value: u64,
+    _tx: &[u8],
+    _index: usize,
+) -> Result<bool, BdkJniError> {
+    Err(BdkJniError::Unsupported(
+        "Script verification is not available on Android".to_string(),
+    ))
+}
+
+/// The master key given either as an xprv or as a mnemonic with an optional passphrase
+fn master_xprv(
+    xprv: Option<String>,
+    mnemonic: Option<String>,
+    password: Option<String>,
+    language: Option<WordlistLanguage>,
+    network: Option<Network>,
+) -> Result<ExtendedPrivKey, BdkJniError> {
+    match (xprv, mnemonic) {
+        (Some(xprv), None) => {
+            let xprv = ExtendedPrivKey::from_str(&slip132::normalize_key(&xprv))?;
+            // Key origins and paths are computed from this key, they'd be wrong for a child key
+            if xprv.depth != 0 {
+                return Err(BdkJniError::Parsing(format!(
+                    "Expected a master key, got a key of depth {}",
+                    xprv.depth
+                )));
+            }
+
+            Ok(xprv)
+        }
+        (None, Some(mnemonic)) => {
+            let network = network.ok_or_else(|| {
+                BdkJniError::Parsing("`network` is required with a mnemonic".to_string())
+            })?;
+            let mnemonic = mnemonic::from_phrase(&mnemonic, language.unwrap_or_default().into())?;
+            let xkey: ExtendedKey = (mnemonic, password).into_extended_key()?;
+
+            xkey.into_xprv(network).ok_or_else(|| {
+                BdkJniError::Parsing("Mnemonic didn't produce a private key".to_string())
+            })
+        }
+        _ => Err(BdkJniError::Parsing(
+            "Exactly one of `xprv` or `mnemonic` must be provided".to_string(),
+        )),
+    }
+}
+
+/// Whether a PSBT input can be signed by `pubkey`, looking at its key paths and scripts
+fn input_spends_key(psbt: &PartiallySignedTransaction, index: usize, pubkey: &PublicKey) -> bool {
+    let input = &psbt.inputs[index];
+    if input.bip32_derivation.contains_key(pubkey) {
+        return true;
+    }
+
+    let pubkey_bytes = pubkey.to_bytes();
+    let in_script = |script: &Option<Script>| {
+        script.as_ref().map_or(false, |s| {
+            s.as_bytes()
+                .windows(pubkey_bytes.len())
+                .any(|w| w == pubkey_bytes.as_slice())
+        })
+    };
+    if in_script(&input.witness_script) || in_script(&input.redeem_script) {
+        return true;
+    }
+
+    let single_key_scripts = vec![
+        Some(Address::p2pkh(pubkey, Network::Bitcoin)),
+        Address::p2wpkh(pubkey, Network::Bitcoin).ok(),
+        Address::p2shwpkh(pubkey, Network::Bitcoin).ok(),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|address| address.script_pubkey())
+    .collect::<Vec<_>>();
+    spent_txout(psbt, index).map_or(false, |txout| {
+        single_key_scripts.contains(&txout.script_pubkey)
+    })
+}
+
+/// Sign every input of a PSBT that can be spent with an xprv or WIF key which isn't part of a
+/// wallet, returning the number of signatures added
+///
+/// Extended keys are matched against the fingerprint and paths in the inputs' `bip32_derivation`.
+/// Keys that are not a master key need an `origin` to be matched. Like `Wallet::sign`, inputs
+/// asking for a sighash other than `SIGHASH_ALL` are refused unless `allow_all_sighashes` is set.
+fn sign_psbt_with_key(
+    psbt: &mut PartiallySignedTransaction,
+    key: &str,
+    origin: Option<KeySource>,
+    allow_all_sighashes: bool,
+    secp: &Secp256k1<All>,
+) -> Result<usize, BdkJniError> {
+    let mut keys = Vec::new();
+
+    if let Ok(xprv) = ExtendedPrivKey::from_str(&slip132::normalize_key(key)) {
+        let (origin_fingerprint, origin_path) =
+            origin.unwrap_or_else(|| (xprv.fingerprint(secp), DerivationPath::master()));
+
+        for (index, input) in psbt.inputs.iter().enumerate() {
+            for (pubkey, (fingerprint, path)) in &input.bip32_derivation {
+                if *fingerprint != origin_fingerprint
+                    || !path.as_ref().starts_with(origin_path.as_ref())
+                {
+                    continue;
+                }
+
+                let path = DerivationPath::from(&path.as_ref()[origin_path.as_ref().len()..]);
+                let derived = xprv.derive_priv(secp, &path)?;
+                if derived.private_key.public_key(secp) == *pubkey {
+                    keys.push((index, derived.private_key));
+                }
+            }
+        }
+    } else {
+        let private_key =
+            PrivateKey::from_wif(key).map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?;
+        let pubkey = private_key.public_key(secp);
+
+        for index in 0..psbt.inputs.len() {
+            if input_spends_key(psbt, index, &pubkey) {
+                keys.push((index, private_key));
+            }
+        }
+    }
+
+    keys.retain(|(index, private_key)| {
+        let input = &psbt.inputs[*index];
+        input.final_script_sig.is_none()
+            && input.final_script_witness.is_none()
+            && !input
+                .partial_sigs
+                .contains_key(&private_key.public_key(secp))
+    });
+
+    if !allow_all_sighashes
+        && keys.iter().any(|(index, _)| {
+            psbt.inputs[*index]
+                .sighash_type
+                .map_or(false, |sighash_type| sighash_type != SigHashType::All)
+        })
+    {
+        return Err(SignerError::NonStandardSighash.into());
+    }
+
+    for (index, private_key) in &keys {
+        private_key.sign(psbt, Some(*index), secp)?;
+    }
+
+    Ok(keys.len())
+}
+
 #[allow(dead_code)]
 fn do_constructor_call(req: BdkRequest) -> Result<serde_json::Value, BdkJniError> {
     use crate::BdkRequest::*;
@@ -245,6 +951,7 @@ fn do_constructor_call(req: BdkRequest) -> Result<serde_json::Value, BdkJniError
         path,
         descriptor,
         change_descriptor,
+        template,
         electrum_url,
         electrum_proxy,
         electrum_retry,
@@ -264,6 +971,22 @@ fn do_constructor_call(req: BdkRequest) -> Result<serde_json::Value, BdkJniError
             name
         );
 
+        let (descriptor, change_descriptor) = match (descriptor, template) {
+            (Some(descriptor), None) => (descriptor, change_descriptor),
+            (None, Some(template)) => {
+                let (descriptor, change_descriptor) = templates::descriptors(template, network)?;
+                (descriptor, Some(change_descriptor))
+            }
+            _ => {
+                return Err(BdkJniError::Parsing(
+                    "Exactly one of `descriptor` or `template` must be provided".to_string(),
+                ))
+            }
+        };
+        let descriptor = slip132::normalize_descriptor(&descriptor)?;
+        let change_descriptor = change_descriptor
+            .map(|d| slip132::normalize_descriptor(&d))
+            .transpose()?;
         let descriptor: &str = descriptor.as_str();
         let change_descriptor: Option<&str> = change_descriptor.as_deref();
 
@@ -276,8 +999,8 @@ fn do_constructor_call(req: BdkRequest) -> Result<serde_json::Value, BdkJniError
         };
         let client = ElectrumBlockchain::from_config(&client_config)?;
 
-        let ptr: OpaquePtr<_> =
-            Wallet::new(descriptor, change_descriptor, network, tree, client)?.into();
+        let wallet = Wallet::new(descriptor, change_descriptor, network, tree, client)?;
+        let ptr: OpaquePtr<WalletLock> = Mutex::new(wallet).into();
 
         serde_json::to_value(&ptr).map_err(BdkJniError::Serialization)
     } else {
@@ -332,12 +1055,15 @@ where
             addressees,
             send_all,
             policy,
+            psbt_format,
+            psbt_version,
             ..
         } => {
             #[derive(Serialize)]
             struct CreateTxResponse {
                 details: TransactionDetails,
-                psbt: String,
+                #[serde(flatten)]
+                psbt: EncodedPsbt,
             }
 
             let addressees = addressees
@@ -389,48 +1115,125 @@ where
             let (psbt, details) = builder.finish()?;
             serde_json::to_value(&CreateTxResponse {
                 details,
-                psbt: base64::encode(&serialize(&psbt)),
+                psbt: encode_psbt(&psbt, psbt_format, psbt_version)?,
             })
             .map_err(BdkJniError::Serialization)
         }
         Sign {
             psbt,
+            psbt_format,
+            psbt_version,
             assume_height,
+            trust_witness_utxo,
+            allow_all_sighashes,
+            remove_partial_sigs,
+            try_finalize,
+            sighash_type,
             ..
         } => {
             #[derive(Serialize)]
             struct SignResponse {
-                psbt: String,
+                #[serde(flatten)]
+                psbt: EncodedPsbt,
                 finalized: bool,
             }
 
-            let psbt =
-                base64::decode(&psbt).map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?;
-            let psbt =
-                &mut deserialize(&psbt).map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?;
-            let finalized = wallet.sign(
+            let (psbt, version) = &mut decode_psbt(&psbt)?;
+
+            if let Some(sighash_type) = sighash_type {
+                let sighash_type = SigHashType::from_str(&sighash_type)
+                    .map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?;
+                for input in psbt.inputs.iter_mut().filter(|i| i.sighash_type.is_none()) {
+                    input.sighash_type = Some(sighash_type);
+                }
+            }
+
+            let already_final = psbt
+                .inputs
+                .iter()
+                .map(|i| i.final_script_sig.is_some() || i.final_script_witness.is_some())
+                .collect::<Vec<_>>();
+            let unsigned_inputs = psbt.inputs.clone();
+
+            let mut finalized = wallet.sign(
                 psbt,
                 SignOptions {
                     assume_height,
-                    ..Default::default()
+                    trust_witness_utxo: trust_witness_utxo.unwrap_or(false),
+                    allow_all_sighashes: allow_all_sighashes.unwrap_or(false),
                 },
             )?;
 
+            // `Wallet::sign` always tries to finalize, so we implement the remaining options on top
+            // of it. Inputs that shouldn't be finalized are restored as they were before signing,
+            // plus the partial signatures left by the signers and the finalizer
+            if try_finalize == Some(false) {
+                for ((input, unsigned), was_final) in psbt
+                    .inputs
+                    .iter_mut()
+                    .zip(unsigned_inputs.into_iter())
+                    .zip(already_final.iter())
+                {
+                    if !was_final {
+                        let partial_sigs = std::mem::take(&mut input.partial_sigs);
+                        *input = unsigned;
+                        input.partial_sigs.extend(partial_sigs);
+                    }
+                }
+                finalized = already_final.iter().all(|f| *f);
+            } else if remove_partial_sigs.unwrap_or(false) {
+                for input in psbt.inputs.iter_mut() {
+                    if input.final_script_sig.is_some() || input.final_script_witness.is_some() {
+                        input.partial_sigs.clear();
+                    }
+                }
+            }
+
             serde_json::to_value(&SignResponse {
-                psbt: base64::encode(&serialize(&psbt)),
+                psbt: encode_psbt(psbt, psbt_format, psbt_version.or(Some(*version)))?,
                 finalized,
             })
             .map_err(BdkJniError::Serialization)
         }
         ExtractPsbt { psbt, .. } => {
-            let psbt =
-                base64::decode(&psbt).map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?;
-            let psbt: PartiallySignedTransaction =
-                deserialize(&psbt).map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?;
+            #[derive(Serialize)]
+            struct ExtractPsbtResponse {
+                transaction: String,
+                txid: String,
+                wtxid: String,
+                vsize: usize,
+                weight: usize,
+                fee: Option<u64>,
+            }
 
-            Ok(json!({
-                "transaction": serialize(&psbt.extract_tx()).to_hex(),
-            }))
+            let (psbt, _) = decode_psbt(&psbt)?;
+
+            let not_finalized = psbt
+                .inputs
+                .iter()
+                .enumerate()
+                .filter(|(_, input)| {
+                    input.final_script_sig.is_none() && input.final_script_witness.is_none()
+                })
+                .map(|(index, _)| index)
+                .collect::<Vec<_>>();
+            if !not_finalized.is_empty() {
+                return Err(BdkJniError::PsbtNotFinalized(not_finalized));
+            }
+
+            let fee = psbt_fee(&psbt);
+            let tx = psbt.extract_tx();
+            let weight = tx.get_weight();
+
+            serde_json::to_value(&ExtractPsbtResponse {
+                transaction: serialize(&tx).to_hex(),
+                txid: tx.txid().to_hex(),
+                wtxid: tx.wtxid().to_hex(),
+                vsize: (weight + 3) / 4,
+                weight,
+                fee,
+            })
+            .map_err(BdkJniError::Serialization)
         }
         Broadcast { raw_tx, .. } => {
             let raw_tx: Vec<u8> =
@@ -462,91 +1265,805 @@ where
             serde_json::to_value(&PublicDescriptorsResponse { external, internal })
                 .map_err(BdkJniError::Serialization)
         }
-        GenerateExtendedKey { .. } => Err(BdkJniError::Unsupported(
-            "Called `do_wallet_call` with a GenerateExtendedKey request".to_string(),
-        )),
-        RestoreExtendedKey { .. } => Err(BdkJniError::Unsupported(
-            "Called `do_wallet_call` with a RestoreExtendedKey request".to_string(),
-        )),
-    };
-
-    resp
-}
-
-#[allow(dead_code)]
-fn do_key_call(req: BdkRequest) -> Result<serde_json::Value, BdkJniError> {
-    use crate::BdkRequest::*;
-
-    let secp = Secp256k1::new();
-
-    match req {
-        GenerateExtendedKey {
-            network,
-            word_count,
+        SweepPrivateKey {
+            key,
             password,
+            fee_rate,
+            ..
         } => {
             #[derive(Serialize)]
-            struct GenerateExtendedKeyResponse {
-                mnemonic: String,
-                xprv: String,
-                fingerprint: String,
+            struct SweepPrivateKeyResponse {
+                transaction: String,
+                txid: String,
+                /// Total value of the swept outputs
+                amount: u64,
+                fee: u64,
             }
-            let mnemonic_type = match word_count {
-                12 => MnemonicType::Words12,
-                _ => MnemonicType::Words24,
-            };
-            let mnemonic: GeneratedKey<_, miniscript::BareCtx> =
-                Mnemonic::generate((mnemonic_type, Language::English)).unwrap();
-            let mnemonic = mnemonic.into_key();
-            let xkey: ExtendedKey = (mnemonic.clone(), password).into_extended_key()?;
-            let xprv = xkey.into_xprv(network).unwrap();
-            let fingerprint = xprv.fingerprint(&secp);
 
-            let resp = &GenerateExtendedKeyResponse {
-                mnemonic: mnemonic.to_string(),
-                xprv: xprv.to_string(),
-                fingerprint: fingerprint.to_string(),
+            let key = if bip38::is_encrypted(&key) {
+                let password = password.ok_or_else(|| {
+                    BdkJniError::Parsing("`password` is required with a BIP38 key".to_string())
+                })?;
+                bip38::decrypt(&key, &password)?
+            } else {
+                PrivateKey::from_wif(key.trim())
+                    .map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?
             };
+            let sweep = sweep::sweep(wallet, &key, FeeRate::from_sat_per_vb(fee_rate))?;
 
-            serde_json::to_value(resp).map_err(BdkJniError::Serialization)
+            serde_json::to_value(&SweepPrivateKeyResponse {
+                transaction: serialize(&sweep.transaction).to_hex(),
+                txid: sweep.transaction.txid().to_hex(),
+                amount: sweep.amount,
+                fee: sweep.fee,
+            })
+            .map_err(BdkJniError::Serialization)
         }
-        RestoreExtendedKey {
-            network,
-            mnemonic,
-            password,
+        SignMessage {
+            address,
+            message,
+            format,
+            ..
         } => {
             #[derive(Serialize)]
-            struct RestoreExtendedKeyResponse {
-                mnemonic: String,
-                xprv: String,
-                fingerprint: String,
+            struct SignMessageResponse {
+                signature: String,
+                format: MessageFormat,
             }
-            let mnemonic = Mnemonic::from_phrase(mnemonic.as_ref(), Language::English).unwrap();
-            let xkey: ExtendedKey = (mnemonic.clone(), password).into_extended_key()?;
-            let xprv = xkey.into_xprv(network).unwrap();
-            let fingerprint = xprv.fingerprint(&secp);
-            let resp = RestoreExtendedKeyResponse {
-                mnemonic: mnemonic.to_string(),
-                xprv: xprv.to_string(),
-                fingerprint: fingerprint.to_string(),
-            };
 
-            serde_json::to_value(resp).map_err(BdkJniError::Serialization)
-        }
-        _ => Err(BdkJniError::Unsupported(
-            "Called `do_key_call` with a non-keys request".to_string(),
-        )),
-    }
-}
+            let address = Address::from_str(&address)
+                .map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?;
+            let format = format.unwrap_or(MessageFormat::Bip137);
 
-/// Expose the JNI interface below
-#[allow(non_snake_case)]
-pub mod bdk_jni {
+            serde_json::to_value(&SignMessageResponse {
+                signature: message::sign(wallet, &address, &message, format)?,
+                format,
+            })
+            .map_err(BdkJniError::Serialization)
+        }
+        CreateReserveProof {
+            message,
+            utxos,
+            psbt_format,
+            psbt_version,
+            ..
+        } => {
+            #[derive(Serialize)]
+            struct CreateReserveProofResponse {
+                #[serde(flatten)]
+                psbt: EncodedPsbt,
+            }
+
+            let utxos: Option<Vec<OutPoint>> = utxos
+                .map(|u| {
+                    u.into_iter()
+                        .map(|s| s.parse())
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .transpose()
+                .map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?;
+            let psbt = reserves::create(wallet, &message, utxos)?;
+
+            serde_json::to_value(&CreateReserveProofResponse {
+                psbt: encode_psbt(&psbt, psbt_format, psbt_version)?,
+            })
+            .map_err(BdkJniError::Serialization)
+        }
+        VerifyReserveProof { psbt, message, .. } => {
+            #[derive(Serialize)]
+            struct VerifyReserveProofResponse {
+                amount: u64,
+            }
+
+            let (psbt, _) = decode_psbt(&psbt)?;
+
+            serde_json::to_value(&VerifyReserveProofResponse {
+                amount: reserves::verify(wallet, &psbt, &message)?,
+            })
+            .map_err(BdkJniError::Serialization)
+        }
+        AddExternalSigner { .. } => Err(BdkJniError::Unsupported(
+            "Called `do_wallet_call` with an AddExternalSigner request".to_string(),
+        )),
+        SignPsbtWithKey { .. } => Err(BdkJniError::Unsupported(
+            "Called `do_wallet_call` with a SignPsbtWithKey request".to_string(),
+        )),
+        ConvertPsbt { .. } => Err(BdkJniError::Unsupported(
+            "Called `do_wallet_call` with a ConvertPsbt request".to_string(),
+        )),
+        CombinePsbts { .. } => Err(BdkJniError::Unsupported(
+            "Called `do_wallet_call` with a CombinePsbts request".to_string(),
+        )),
+        EncodeUrPsbt { .. } => Err(BdkJniError::Unsupported(
+            "Called `do_wallet_call` with an EncodeUrPsbt request".to_string(),
+        )),
+        NewUrDecoder { .. } | ReceiveUrPart { .. } | FreeUrDecoder { .. } => {
+            Err(BdkJniError::Unsupported(
+                "Called `do_wallet_call` with a UR decoder request".to_string(),
+            ))
+        }
+        GenerateExtendedKey { .. } => Err(BdkJniError::Unsupported(
+            "Called `do_wallet_call` with a GenerateExtendedKey request".to_string(),
+        )),
+        RestoreExtendedKey { .. } => Err(BdkJniError::Unsupported(
+            "Called `do_wallet_call` with a RestoreExtendedKey request".to_string(),
+        )),
+        ValidateMnemonic { .. } => Err(BdkJniError::Unsupported(
+            "Called `do_wallet_call` with a ValidateMnemonic request".to_string(),
+        )),
+        MnemonicFinalWords { .. } => Err(BdkJniError::Unsupported(
+            "Called `do_wallet_call` with a MnemonicFinalWords request".to_string(),
+        )),
+        DeriveExtendedKey { .. } => Err(BdkJniError::Unsupported(
+            "Called `do_wallet_call` with a DeriveExtendedKey request".to_string(),
+        )),
+        DescriptorTemplate { .. } => Err(BdkJniError::Unsupported(
+            "Called `do_wallet_call` with a DescriptorTemplate request".to_string(),
+        )),
+        MultisigDescriptor { .. } => Err(BdkJniError::Unsupported(
+            "Called `do_wallet_call` with a MultisigDescriptor request".to_string(),
+        )),
+        ConvertExtendedKey { .. } => Err(BdkJniError::Unsupported(
+            "Called `do_wallet_call` with a ConvertExtendedKey request".to_string(),
+        )),
+        Bip85 { .. } => Err(BdkJniError::Unsupported(
+            "Called `do_wallet_call` with a Bip85 request".to_string(),
+        )),
+        Slip39Split { .. } | Slip39Combine { .. } => Err(BdkJniError::Unsupported(
+            "Called `do_wallet_call` with a SLIP-39 request".to_string(),
+        )),
+        SeedXorSplit { .. } | SeedXorCombine { .. } => Err(BdkJniError::Unsupported(
+            "Called `do_wallet_call` with a Seed XOR request".to_string(),
+        )),
+        VerifyPassphrase { .. } => Err(BdkJniError::Unsupported(
+            "Called `do_wallet_call` with a VerifyPassphrase request".to_string(),
+        )),
+        Bip38Encrypt { .. } | Bip38Decrypt { .. } => Err(BdkJniError::Unsupported(
+            "Called `do_wallet_call` with a BIP38 request".to_string(),
+        )),
+        VerifyMessage { .. } => Err(BdkJniError::Unsupported(
+            "Called `do_wallet_call` with a VerifyMessage request".to_string(),
+        )),
+        DiscoverAccounts { .. } => Err(BdkJniError::Unsupported(
+            "Called `do_wallet_call` with a DiscoverAccounts request".to_string(),
+        )),
+    };
+
+    resp
+}
+
+#[allow(dead_code)]
+fn do_psbt_call(req: BdkRequest) -> Result<serde_json::Value, BdkJniError> {
+    use crate::BdkRequest::*;
+
+    let secp = Secp256k1::new();
+
+    match req {
+        SignPsbtWithKey {
+            psbt,
+            psbt_format,
+            psbt_version,
+            key,
+            origin,
+            allow_all_sighashes,
+        } => {
+            #[derive(Serialize)]
+            struct SignPsbtWithKeyResponse {
+                #[serde(flatten)]
+                psbt: EncodedPsbt,
+                signatures: usize,
+            }
+
+            let (mut psbt, version) = decode_psbt(&psbt)?;
+            let origin = origin.as_deref().map(parse_key_origin).transpose()?;
+
+            let signatures = sign_psbt_with_key(
+                &mut psbt,
+                &key,
+                origin,
+                allow_all_sighashes.unwrap_or(false),
+                &secp,
+            )?;
+
+            serde_json::to_value(&SignPsbtWithKeyResponse {
+                psbt: encode_psbt(&psbt, psbt_format, psbt_version.or(Some(version)))?,
+                signatures,
+            })
+            .map_err(BdkJniError::Serialization)
+        }
+        ConvertPsbt {
+            psbt,
+            psbt_format,
+            psbt_version,
+        } => serde_json::to_value(&encode_psbt(
+            &decode_psbt(&psbt)?.0,
+            psbt_format,
+            Some(psbt_version),
+        )?)
+        .map_err(BdkJniError::Serialization),
+        CombinePsbts {
+            psbts,
+            psbt_format,
+            psbt_version,
+        } => {
+            let mut psbts = psbts.iter().map(decode_psbt);
+            let (mut combined, version) = psbts.next().ok_or_else(|| {
+                BdkJniError::Parsing("At least one PSBT is required".to_string())
+            })??;
+            for psbt in psbts {
+                combined
+                    .merge(psbt?.0)
+                    .map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?;
+            }
+
+            serde_json::to_value(&encode_psbt(
+                &combined,
+                psbt_format,
+                psbt_version.or(Some(version)),
+            )?)
+            .map_err(BdkJniError::Serialization)
+        }
+        EncodeUrPsbt {
+            psbt,
+            max_fragment_length,
+            min_parts,
+        } => {
+            #[derive(Serialize)]
+            struct EncodeUrPsbtResponse {
+                parts: Vec<String>,
+                fragment_count: usize,
+            }
+
+            let (psbt, version) = decode_psbt(&psbt)?;
+            let (parts, fragment_count) = bcur::encode_psbt(
+                &psbt_v2::from_v0(serialize(&psbt), version)?,
+                max_fragment_length.unwrap_or(200),
+                min_parts.unwrap_or(0),
+            )?;
+
+            serde_json::to_value(&EncodeUrPsbtResponse {
+                parts,
+                fragment_count,
+            })
+            .map_err(BdkJniError::Serialization)
+        }
+        NewUrDecoder {} => {
+            let ptr: OpaquePtr<_> = Mutex::new(UrPsbtDecoder::default()).into();
+            serde_json::to_value(&ptr).map_err(BdkJniError::Serialization)
+        }
+        _ => Err(BdkJniError::Unsupported(
+            "Called `do_psbt_call` with a non-psbt request".to_string(),
+        )),
+    }
+}
+
+#[allow(dead_code)]
+fn do_ur_decoder_call(
+    decoder: &mut UrPsbtDecoder,
+    req: BdkRequest,
+) -> Result<serde_json::Value, BdkJniError> {
+    use crate::BdkRequest::*;
+
+    match req {
+        ReceiveUrPart {
+            part,
+            psbt_format,
+            psbt_version,
+            ..
+        } => {
+            #[derive(Serialize)]
+            struct ReceiveUrPartResponse {
+                complete: bool,
+                progress: f32,
+                received: usize,
+                fragment_count: Option<usize>,
+                #[serde(flatten)]
+                psbt: Option<EncodedPsbt>,
+            }
+
+            decoder.receive(&part)?;
+            let psbt = decoder
+                .psbt()
+                .map(|psbt| decode_psbt(&PsbtData::Bytes(psbt.to_vec())))
+                .transpose()?
+                .map(|(psbt, version)| {
+                    encode_psbt(&psbt, psbt_format, psbt_version.or(Some(version)))
+                })
+                .transpose()?;
+
+            serde_json::to_value(&ReceiveUrPartResponse {
+                complete: psbt.is_some(),
+                progress: decoder.progress(),
+                received: decoder.received(),
+                fragment_count: decoder.fragment_count(),
+                psbt,
+            })
+            .map_err(BdkJniError::Serialization)
+        }
+        FreeUrDecoder { .. } => Ok(serde_json::Value::Null),
+        _ => Err(BdkJniError::Unsupported(
+            "Called `do_ur_decoder_call` with a non-decoder request".to_string(),
+        )),
+    }
+}
+
+#[allow(dead_code)]
+fn do_key_call(req: BdkRequest) -> Result<serde_json::Value, BdkJniError> {
+    use crate::BdkRequest::*;
+
+    let secp = Secp256k1::new();
+
+    match req {
+        GenerateExtendedKey {
+            network,
+            word_count,
+            password,
+            language,
+            entropy,
+            entropy_source,
+            mix_system_entropy,
+        } => {
+            #[derive(Serialize)]
+            struct GenerateExtendedKeyResponse {
+                mnemonic: String,
+                xprv: String,
+                fingerprint: String,
+                entropy: String,
+            }
+            let mnemonic_type = mnemonic::mnemonic_type(word_count)?;
+            let language: Language = language.unwrap_or_default().into();
+            let mnemonic = match entropy {
+                Some(entropy) => {
+                    let entropy = mnemonic::entropy_from_user(
+                        &entropy,
+                        entropy_source.unwrap_or(EntropySource::Hex),
+                        mnemonic_type.entropy_bits() / 8,
+                        mix_system_entropy.unwrap_or(false),
+                    )?;
+                    Mnemonic::from_entropy(&entropy, language)
+                        .map_err(|e| BdkJniError::Mnemonic(e.to_string()))?
+                }
+                None => {
+                    let mnemonic: GeneratedKey<_, miniscript::BareCtx> =
+                        Mnemonic::generate((mnemonic_type, language)).unwrap();
+                    mnemonic.into_key()
+                }
+            };
+            let xkey: ExtendedKey = (mnemonic.clone(), password).into_extended_key()?;
+            let xprv = xkey.into_xprv(network).unwrap();
+            let fingerprint = xprv.fingerprint(&secp);
+
+            let resp = &GenerateExtendedKeyResponse {
+                mnemonic: mnemonic.to_string(),
+                xprv: xprv.to_string(),
+                fingerprint: fingerprint.to_string(),
+                entropy: mnemonic.entropy().to_hex(),
+            };
+
+            serde_json::to_value(resp).map_err(BdkJniError::Serialization)
+        }
+        RestoreExtendedKey {
+            network,
+            mnemonic,
+            password,
+            language,
+        } => {
+            #[derive(Serialize)]
+            struct RestoreExtendedKeyResponse {
+                mnemonic: String,
+                xprv: String,
+                fingerprint: String,
+                entropy: String,
+            }
+            let language: Language = language.unwrap_or_default().into();
+            let mnemonic = mnemonic::from_phrase(&mnemonic, language)?;
+            let xkey: ExtendedKey = (mnemonic.clone(), password).into_extended_key()?;
+            let xprv = xkey.into_xprv(network).unwrap();
+            let fingerprint = xprv.fingerprint(&secp);
+            let resp = RestoreExtendedKeyResponse {
+                mnemonic: mnemonic.to_string(),
+                xprv: xprv.to_string(),
+                fingerprint: fingerprint.to_string(),
+                entropy: mnemonic.entropy().to_hex(),
+            };
+
+            serde_json::to_value(resp).map_err(BdkJniError::Serialization)
+        }
+        ValidateMnemonic { mnemonic, language } => {
+            serde_json::to_value(mnemonic::validate(&mnemonic, language))
+                .map_err(BdkJniError::Serialization)
+        }
+        DeriveExtendedKey {
+            xprv,
+            mnemonic,
+            password,
+            language,
+            network,
+            path,
+        } => {
+            #[derive(Serialize)]
+            struct DeriveExtendedKeyResponse {
+                xprv: String,
+                xpub: String,
+                fingerprint: String,
+                path: String,
+                /// `[fingerprint/path]xpub`
+                key_origin: String,
+            }
+
+            let master = master_xprv(xprv, mnemonic, password, language, network)?;
+            let fingerprint = master.fingerprint(&secp);
+            let path = DerivationPath::from_str(&path)?;
+            let xprv = master.derive_priv(&secp, &path)?;
+            let xpub = ExtendedPubKey::from_private(&secp, &xprv);
+
+            let resp = DeriveExtendedKeyResponse {
+                xprv: xprv.to_string(),
+                xpub: xpub.to_string(),
+                fingerprint: fingerprint.to_string(),
+                path: path.to_string(),
+                key_origin: format!("{}{}", format_key_origin(fingerprint, &path), xpub),
+            };
+
+            serde_json::to_value(resp).map_err(BdkJniError::Serialization)
+        }
+        DescriptorTemplate { network, template } => {
+            #[derive(Serialize)]
+            struct DescriptorTemplateResponse {
+                descriptor: String,
+                change_descriptor: String,
+            }
+
+            let (descriptor, change_descriptor) = templates::descriptors(template, network)?;
+
+            serde_json::to_value(&DescriptorTemplateResponse {
+                descriptor,
+                change_descriptor,
+            })
+            .map_err(BdkJniError::Serialization)
+        }
+        MultisigDescriptor {
+            network,
+            threshold,
+            script_type,
+            cosigners,
+            xprv,
+            mnemonic,
+            password,
+            language,
+            account,
+        } => {
+            let master = match (&xprv, &mnemonic) {
+                (None, None) => None,
+                _ => Some(master_xprv(
+                    xprv,
+                    mnemonic,
+                    password,
+                    language,
+                    Some(network),
+                )?),
+            };
+            let descriptors = multisig::descriptors(
+                threshold,
+                script_type,
+                &cosigners,
+                master,
+                account.unwrap_or(0),
+                network,
+            )?;
+
+            serde_json::to_value(&descriptors).map_err(BdkJniError::Serialization)
+        }
+        Bip85 {
+            xprv,
+            mnemonic,
+            password,
+            language,
+            network,
+            application,
+            index,
+        } => {
+            #[derive(Serialize)]
+            struct Bip85Response {
+                /// Mnemonic, hex entropy, xprv or WIF depending on the application
+                derived: String,
+                path: String,
+            }
+
+            let master = master_xprv(xprv, mnemonic, password, language, network)?;
+            let resp = Bip85Response {
+                derived: bip85::derive(&master, application, index)?,
+                path: bip85::derivation_path(application, index)?.to_string(),
+            };
+
+            serde_json::to_value(resp).map_err(BdkJniError::Serialization)
+        }
+        Slip39Split {
+            master_secret,
+            mnemonic,
+            language,
+            group_threshold,
+            groups,
+            passphrase,
+            iteration_exponent,
+        } => {
+            #[derive(Serialize)]
+            struct Slip39SplitResponse {
+                /// Share mnemonics of each group
+                groups: Vec<Vec<String>>,
+            }
+
+            let master_secret = match (master_secret, mnemonic) {
+                (Some(master_secret), None) => Vec::<u8>::from_hex(&master_secret)
+                    .map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?,
+                (None, Some(mnemonic)) => {
+                    mnemonic::from_phrase(&mnemonic, language.unwrap_or_default().into())?
+                        .entropy()
+                        .to_vec()
+                }
+                _ => {
+                    return Err(BdkJniError::Parsing(
+                        "Exactly one of `master_secret` or `mnemonic` must be provided".to_string(),
+                    ))
+                }
+            };
+            let groups = slip39::split(
+                &master_secret,
+                group_threshold,
+                &groups,
+                passphrase.as_deref().unwrap_or_default(),
+                iteration_exponent.unwrap_or(0),
+            )?;
+
+            serde_json::to_value(&Slip39SplitResponse { groups })
+                .map_err(BdkJniError::Serialization)
+        }
+        Slip39Combine {
+            shares,
+            passphrase,
+            language,
+        } => {
+            #[derive(Serialize)]
+            struct Slip39CombineResponse {
+                master_secret: String,
+                /// The BIP39 mnemonic encoding the master secret, only missing for secret lengths
+                /// BIP39 doesn't support (e.g. 18 bytes)
+                mnemonic: Option<String>,
+            }
+
+            let master_secret =
+                slip39::combine(&shares, passphrase.as_deref().unwrap_or_default())?;
+            let mnemonic =
+                Mnemonic::from_entropy(&master_secret, language.unwrap_or_default().into())
+                    .ok()
+                    .map(|m| m.to_string());
+
+            serde_json::to_value(&Slip39CombineResponse {
+                master_secret: master_secret.to_hex(),
+                mnemonic,
+            })
+            .map_err(BdkJniError::Serialization)
+        }
+        SeedXorSplit {
+            mnemonic,
+            language,
+            parts,
+        } => {
+            #[derive(Serialize)]
+            struct SeedXorSplitResponse {
+                parts: Vec<String>,
+            }
+
+            let mnemonic = mnemonic::from_phrase(&mnemonic, language.unwrap_or_default().into())?;
+            let parts = mnemonic::xor_split(&mnemonic, parts)?
+                .iter()
+                .map(|part| part.to_string())
+                .collect();
+
+            serde_json::to_value(&SeedXorSplitResponse { parts })
+                .map_err(BdkJniError::Serialization)
+        }
+        SeedXorCombine { parts, language } => {
+            #[derive(Serialize)]
+            struct SeedXorCombineResponse {
+                mnemonic: String,
+            }
+
+            let parts = parts
+                .iter()
+                .map(|part| mnemonic::from_phrase(part, language.unwrap_or_default().into()))
+                .collect::<Result<Vec<_>, _>>()?;
+            let mnemonic = mnemonic::xor_combine(&parts)?;
+
+            serde_json::to_value(&SeedXorCombineResponse {
+                mnemonic: mnemonic.to_string(),
+            })
+            .map_err(BdkJniError::Serialization)
+        }
+        VerifyPassphrase {
+            network,
+            mnemonic,
+            password,
+            language,
+            expected_fingerprint,
+        } => {
+            #[derive(Serialize)]
+            struct FirstAddress {
+                template: Template,
+                path: String,
+                address: String,
+            }
+            #[derive(Serialize)]
+            struct VerifyPassphraseResponse {
+                fingerprint: String,
+                /// Whether `fingerprint` is the expected one, if any was given
+                matches: Option<bool>,
+                addresses: Vec<FirstAddress>,
+            }
+
+            let master = master_xprv(None, Some(mnemonic), password, language, Some(network))?;
+            let fingerprint = master.fingerprint(&secp);
+            let matches = expected_fingerprint
+                .map(|expected| Fingerprint::from_str(expected.trim()))
+                .transpose()
+                .map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?
+                .map(|expected| expected == fingerprint);
+            let addresses = Template::ALL
+                .iter()
+                .map(|template| {
+                    let (path, address) = templates::first_address(*template, &master)?;
+                    Ok(FirstAddress {
+                        template: *template,
+                        path: path.to_string(),
+                        address: address.to_string(),
+                    })
+                })
+                .collect::<Result<Vec<_>, BdkJniError>>()?;
+
+            serde_json::to_value(&VerifyPassphraseResponse {
+                fingerprint: fingerprint.to_string(),
+                matches,
+                addresses,
+            })
+            .map_err(BdkJniError::Serialization)
+        }
+        Bip38Encrypt { wif, password } => {
+            #[derive(Serialize)]
+            struct Bip38EncryptResponse {
+                key: String,
+            }
+
+            let private_key = PrivateKey::from_wif(wif.trim())
+                .map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?;
+
+            serde_json::to_value(&Bip38EncryptResponse {
+                key: bip38::encrypt(&private_key, &password),
+            })
+            .map_err(BdkJniError::Serialization)
+        }
+        Bip38Decrypt { key, password } => {
+            #[derive(Serialize)]
+            struct Bip38DecryptResponse {
+                wif: String,
+                compressed: bool,
+            }
+
+            let private_key = bip38::decrypt(&key, &password)?;
+
+            serde_json::to_value(&Bip38DecryptResponse {
+                wif: private_key.to_wif(),
+                compressed: private_key.compressed,
+            })
+            .map_err(BdkJniError::Serialization)
+        }
+        VerifyMessage {
+            address,
+            message,
+            signature,
+        } => {
+            #[derive(Serialize)]
+            struct VerifyMessageResponse {
+                valid: bool,
+                format: MessageFormat,
+            }
+
+            let address = Address::from_str(&address)
+                .map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?;
+            let (valid, format) = message::verify(&address, &message, &signature)?;
+
+            serde_json::to_value(&VerifyMessageResponse { valid, format })
+                .map_err(BdkJniError::Serialization)
+        }
+        DiscoverAccounts {
+            network,
+            xprv,
+            mnemonic,
+            password,
+            language,
+            templates,
+            accounts,
+            electrum_url,
+            electrum_proxy,
+            electrum_retry,
+            electrum_timeout,
+            electrum_stop_gap,
+        } => {
+            #[derive(Serialize)]
+            struct DiscoverAccountsResponse {
+                accounts: Vec<discovery::UsedAccount>,
+            }
+
+            let master = master_xprv(xprv, mnemonic, password, language, Some(network))?;
+            let templates = templates.unwrap_or_else(|| Template::ALL.to_vec());
+            let client_config = ElectrumBlockchainConfig {
+                url: electrum_url,
+                socks5: electrum_proxy,
+                retry: electrum_retry,
+                timeout: electrum_timeout,
+                stop_gap: electrum_stop_gap,
+            };
+            let accounts =
+                discovery::discover(&master, &templates, accounts.unwrap_or(5), &client_config)?;
+
+            serde_json::to_value(&DiscoverAccountsResponse { accounts })
+                .map_err(BdkJniError::Serialization)
+        }
+        ConvertExtendedKey { key, version } => {
+            #[derive(Serialize)]
+            struct ConvertExtendedKeyResponse {
+                key: String,
+                /// Version of the original key
+                original_version: KeyVersion,
+                private: bool,
+            }
+
+            let decoded = slip132::decode(&key)?;
+            let resp = ConvertExtendedKeyResponse {
+                key: decoded.encode(version),
+                original_version: decoded.version,
+                private: decoded.private,
+            };
+
+            serde_json::to_value(resp).map_err(BdkJniError::Serialization)
+        }
+        MnemonicFinalWords { mnemonic, language } => {
+            #[derive(Serialize)]
+            struct MnemonicFinalWordsResponse {
+                language: WordlistLanguage,
+                words: Vec<String>,
+            }
+
+            let language = language
+                .or_else(|| {
+                    let mnemonic = mnemonic.to_lowercase();
+                    let words = mnemonic.split_whitespace().collect::<Vec<_>>();
+                    mnemonic::detect_language(&words)
+                })
+                .unwrap_or_default();
+            let words = mnemonic::final_words(&mnemonic, language)?;
+
+            serde_json::to_value(&MnemonicFinalWordsResponse { language, words })
+                .map_err(BdkJniError::Serialization)
+        }
+        _ => Err(BdkJniError::Unsupported(
+            "Called `do_key_call` with a non-keys request".to_string(),
+        )),
+    }
+}
+
+/// Expose the JNI interface below
+#[allow(non_snake_case)]
+pub mod bdk_jni {
+    use std::cell::RefCell;
     use std::ffi::CString;
+    use std::sync::Arc;
 
-    use jni::objects::{JClass, JObject, JString};
+    use jni::objects::{GlobalRef, JClass, JObject, JString, JValue};
     use jni::sys::jstring;
-    use jni::JNIEnv;
+    use jni::{JNIEnv, JavaVM};
+
+    use bdk::bitcoin::secp256k1::{Message, Signature};
+    use bdk::signer::{SignerId, SignerOrdering};
 
     use crate::*;
 
@@ -579,14 +2096,7 @@ pub mod bdk_jni {
         }
     }
 
-    #[no_mangle]
-    pub unsafe extern "C" fn Java_org_bitcoindevkit_bdkjni_Lib_call(
-        env: JNIEnv,
-        _: JClass,
-        incoming_stringj: JString,
-    ) -> jstring {
-        use crate::BdkRequest::*;
-
+    fn read_request(env: &JNIEnv, incoming_stringj: JString) -> Result<BdkRequest, JNIError> {
         #[cfg(target_os = "android")]
         android_logger::init_once(
             android_logger::Config::default().with_min_level(log::Level::Debug),
@@ -595,25 +2105,60 @@ pub mod bdk_jni {
         let incoming_string: String = match env.get_string(incoming_stringj) {
             Ok(string) => string.into(),
             Err(e) => {
-                return JNIError {
+                return Err(JNIError {
                     error: format!("Invalid input string: {:?}", e),
                     code: -1001,
-                }
-                .into_string(&env)
+                })
             }
         };
 
-        let deser = match serde_json::from_str::<BdkRequest>(incoming_string.as_str()) {
-            Ok(req) => req,
+        serde_json::from_str::<BdkRequest>(incoming_string.as_str()).map_err(|e| JNIError {
+            error: format!("Cannot deserialize input: {:?}", e),
+            code: -1003,
+        })
+    }
+
+    fn write_response(
+        env: &JNIEnv,
+        response_result: Result<serde_json::Value, BdkJniError>,
+    ) -> jstring {
+        let final_string = match response_result {
+            Ok(stuff) => serde_json::to_string(&stuff),
+            Err(e) => {
+                return JNIError {
+                    error: format!("{:?}", e),
+                    code: -1,
+                }
+                .into_string(env)
+            }
+        };
+        let final_string = match final_string {
+            Ok(string) => string,
             Err(e) => {
                 return JNIError {
-                    error: format!("Cannot deserialize input: {:?}", e),
-                    code: -1003,
+                    error: format!("{:?}", e),
+                    code: -1004,
                 }
-                .into_string(&env)
+                .into_string(env)
             }
         };
 
+        string_to_jstring(env, &final_string).unwrap_or(JObject::null().into_inner())
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn Java_org_bitcoindevkit_bdkjni_Lib_call(
+        env: JNIEnv,
+        _: JClass,
+        incoming_stringj: JString,
+    ) -> jstring {
+        use crate::BdkRequest::*;
+
+        let deser = match read_request(&env, incoming_stringj) {
+            Ok(req) => req,
+            Err(e) => return e.into_string(&env),
+        };
+
         let response_result = match &deser {
             Constructor { .. } => do_constructor_call(deser),
             Destructor { ref wallet }
@@ -627,14 +2172,22 @@ pub mod bdk_jni {
             | Sign { ref wallet, .. }
             | ExtractPsbt { ref wallet, .. }
             | Broadcast { ref wallet, .. }
-            | PublicDescriptors { ref wallet } => {
-                if let Ok(w) =
-                    OpaquePtr::<Wallet<ElectrumBlockchain, sled::Tree>>::convert_from(wallet)
-                {
+            | PublicDescriptors { ref wallet }
+            | SweepPrivateKey { ref wallet, .. }
+            | SignMessage { ref wallet, .. }
+            | CreateReserveProof { ref wallet, .. }
+            | VerifyReserveProof { ref wallet, .. } => {
+                if let Ok(w) = OpaquePtr::<WalletLock>::convert_from(wallet) {
                     let drop_wallet = matches!(deser, Destructor { .. });
-                    let result = do_wallet_call(w.as_ref(), deser);
+                    let result = do_wallet_call(
+                        &w.as_ref().lock().unwrap_or_else(PoisonError::into_inner),
+                        deser,
+                    );
 
                     if drop_wallet {
+                        // Wait for the calls already inside the wallet before freeing it, the JVM
+                        // must not send new ones once the destructor is called
+                        drop(w.as_ref().lock());
                         let _ = w.move_out();
                     }
 
@@ -645,31 +2198,274 @@ pub mod bdk_jni {
                     ))
                 }
             }
-            GenerateExtendedKey { .. } | RestoreExtendedKey { .. } => do_key_call(deser),
+            AddExternalSigner { .. } => Err(BdkJniError::Unsupported(
+                "AddExternalSigner must be sent through `call_with_signer`".to_string(),
+            )),
+            SignPsbtWithKey { .. }
+            | ConvertPsbt { .. }
+            | CombinePsbts { .. }
+            | EncodeUrPsbt { .. }
+            | NewUrDecoder { .. } => do_psbt_call(deser),
+            ReceiveUrPart { ref decoder, .. } | FreeUrDecoder { ref decoder } => {
+                if let Ok(d) = OpaquePtr::<Mutex<UrPsbtDecoder>>::convert_from(decoder) {
+                    let drop_decoder = matches!(deser, FreeUrDecoder { .. });
+                    let result = do_ur_decoder_call(
+                        &mut d.as_ref().lock().unwrap_or_else(PoisonError::into_inner),
+                        deser,
+                    );
+
+                    if drop_decoder {
+                        let _ = d.move_out();
+                    }
+
+                    result
+                } else {
+                    Err(BdkJniError::Unsupported(
+                        "Invalid decoder pointer".to_string(),
+                    ))
+                }
+            }
+            GenerateExtendedKey { .. }
+            | RestoreExtendedKey { .. }
+            | ValidateMnemonic { .. }
+            | MnemonicFinalWords { .. }
+            | DeriveExtendedKey { .. }
+            | DescriptorTemplate { .. }
+            | MultisigDescriptor { .. }
+            | ConvertExtendedKey { .. }
+            | Bip85 { .. }
+            | Slip39Split { .. }
+            | Slip39Combine { .. }
+            | SeedXorSplit { .. }
+            | SeedXorCombine { .. }
+            | VerifyPassphrase { .. }
+            | Bip38Encrypt { .. }
+            | Bip38Decrypt { .. }
+            | VerifyMessage { .. }
+            | DiscoverAccounts { .. } => do_key_call(deser),
         };
 
-        let final_string = match response_result {
-            Ok(stuff) => serde_json::to_string(&stuff),
-            Err(e) => {
-                return JNIError {
-                    error: format!("{:?}", e),
-                    code: -1,
+        write_response(&env, with_signer_exception(response_result))
+    }
+
+    thread_local! {
+        /// Description of the last exception thrown by an `ExternalSigner` on this thread, since
+        /// `SignerError` can't carry it
+        static SIGNER_EXCEPTION: RefCell<Option<String>> = RefCell::new(None);
+    }
+
+    /// Clear the pending Java exception, returning its description
+    fn take_exception(env: &JNIEnv) -> Option<String> {
+        let exception = env.exception_occurred().ok()?;
+        if exception.is_null() {
+            return None;
+        }
+        env.exception_clear().ok()?;
+
+        let description = env
+            .call_method(exception, "toString", "()Ljava/lang/String;", &[])
+            .ok()?
+            .l()
+            .ok()?;
+        env.get_string(description.into()).ok().map(Into::into)
+    }
+
+    /// Replace the error of a call that failed because an `ExternalSigner` threw with the
+    /// exception it threw
+    fn with_signer_exception(
+        result: Result<serde_json::Value, BdkJniError>,
+    ) -> Result<serde_json::Value, BdkJniError> {
+        let exception = SIGNER_EXCEPTION.with(|last| last.borrow_mut().take());
+        match (result, exception) {
+            (Err(_), Some(exception)) => Err(BdkJniError::ExternalSigner(exception)),
+            (result, _) => result,
+        }
+    }
+
+    /// Signer that delegates signing to an `ExternalSigner` object living in the JVM, so that
+    /// private keys never have to enter Rust
+    struct JvmSigner {
+        vm: JavaVM,
+        callback: GlobalRef,
+        fingerprint: Fingerprint,
+    }
+
+    impl std::fmt::Debug for JvmSigner {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("JvmSigner")
+                .field("fingerprint", &self.fingerprint)
+                .finish()
+        }
+    }
+
+    impl JvmSigner {
+        /// Ask the JVM for a DER-encoded signature of `sighash`
+        fn request_signature(
+            &self,
+            sighash: &SigHash,
+            path: &DerivationPath,
+        ) -> Result<Signature, SignerError> {
+            let env = self
+                .vm
+                .attach_current_thread()
+                .map_err(|_| SignerError::UserCanceled)?;
+
+            let call = || -> jni::errors::Result<String> {
+                let sighash = env.new_string(sighash[..].to_hex())?;
+                let fingerprint = env.new_string(self.fingerprint.to_string())?;
+                let path = env.new_string(path.to_string())?;
+
+                let result = env
+                    .call_method(
+                        self.callback.as_obj(),
+                        "sign",
+                        "(Ljava/lang/String;Ljava/lang/String;Ljava/lang/String;)Ljava/lang/String;",
+                        &[
+                            JValue::Object(sighash.into()),
+                            JValue::Object(fingerprint.into()),
+                            JValue::Object(path.into()),
+                        ],
+                    )?
+                    .l()?;
+                Ok(env.get_string(result.into())?.into())
+            };
+
+            let signature = match call() {
+                Ok(signature) => signature,
+                Err(e) => {
+                    let exception = take_exception(&env).unwrap_or_else(|| format!("{:?}", e));
+                    debug!("External signer failed: {}", exception);
+                    SIGNER_EXCEPTION.with(|last| *last.borrow_mut() = Some(exception));
+                    return Err(SignerError::UserCanceled);
                 }
-                .into_string(&env)
+            };
+
+            let signature: Vec<u8> =
+                FromHex::from_hex(&signature).map_err(|_| SignerError::InvalidKey)?;
+            Signature::from_der(&signature).map_err(|_| SignerError::InvalidKey)
+        }
+    }
+
+    impl Signer for JvmSigner {
+        fn sign(
+            &self,
+            psbt: &mut PartiallySignedTransaction,
+            input_index: Option<usize>,
+            secp: &Secp256k1<All>,
+        ) -> Result<(), SignerError> {
+            let input_index = input_index.ok_or(SignerError::InputIndexOutOfRange)?;
+            let input = psbt
+                .inputs
+                .get(input_index)
+                .ok_or(SignerError::InputIndexOutOfRange)?;
+            if input.final_script_sig.is_some() || input.final_script_witness.is_some() {
+                return Ok(());
             }
+
+            let keys = input
+                .bip32_derivation
+                .iter()
+                .filter(|(pubkey, (fingerprint, _))| {
+                    *fingerprint == self.fingerprint && !input.partial_sigs.contains_key(pubkey)
+                })
+                .map(|(pubkey, (_, path))| (*pubkey, path.clone()))
+                .collect::<Vec<_>>();
+            if keys.is_empty() {
+                return Ok(());
+            }
+
+            let (sighash, sighash_type) = input_sighash(psbt, input_index)?;
+            let message = Message::from_slice(&sighash[..]).expect("Sighashes are 32 bytes");
+
+            for (pubkey, path) in keys {
+                let signature = self.request_signature(&sighash, &path)?;
+                secp.verify(&message, &signature, &pubkey.key)
+                    .map_err(|_| SignerError::InvalidKey)?;
+
+                let mut final_signature = signature.serialize_der().to_vec();
+                final_signature.push(sighash_type.as_u32() as u8);
+                psbt.inputs[input_index]
+                    .partial_sigs
+                    .insert(pubkey, final_signature);
+            }
+
+            Ok(())
+        }
+
+        fn sign_whole_tx(&self) -> bool {
+            false
+        }
+
+        fn id(&self, _secp: &Secp256k1<All>) -> SignerId {
+            SignerId::Fingerprint(self.fingerprint)
+        }
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn Java_org_bitcoindevkit_bdkjni_Lib_call_1with_1signer(
+        env: JNIEnv,
+        _: JClass,
+        incoming_stringj: JString,
+        signer: JObject,
+    ) -> jstring {
+        use crate::BdkRequest::*;
+
+        let deser = match read_request(&env, incoming_stringj) {
+            Ok(req) => req,
+            Err(e) => return e.into_string(&env),
         };
-        let final_string = match final_string {
-            Ok(string) => string,
-            Err(e) => {
-                return JNIError {
-                    error: format!("{:?}", e),
-                    code: -1004,
+
+        let response_result = match deser {
+            AddExternalSigner {
+                ref wallet,
+                ref fingerprint,
+                keychain,
+                ordering,
+            } => {
+                let build_signer = || -> Result<JvmSigner, BdkJniError> {
+                    let fingerprint = Fingerprint::from_str(fingerprint)
+                        .map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?;
+                    let vm = env
+                        .get_java_vm()
+                        .map_err(|e| BdkJniError::Unsupported(format!("{:?}", e)))?;
+                    let callback = env
+                        .new_global_ref(signer)
+                        .map_err(|e| BdkJniError::Unsupported(format!("{:?}", e)))?;
+
+                    Ok(JvmSigner {
+                        vm,
+                        callback,
+                        fingerprint,
+                    })
+                };
+
+                match (
+                    OpaquePtr::<WalletLock>::convert_from(wallet),
+                    build_signer(),
+                ) {
+                    (Ok(w), Ok(signer)) => {
+                        w.as_ref()
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .add_signer(
+                                keychain.unwrap_or(KeychainKind::External),
+                                SignerOrdering(ordering.unwrap_or(200)),
+                                Arc::new(signer),
+                            );
+                        Ok(serde_json::Value::Null)
+                    }
+                    (Err(_), _) => Err(BdkJniError::Unsupported(
+                        "Invalid wallet pointer".to_string(),
+                    )),
+                    (_, Err(e)) => Err(e),
                 }
-                .into_string(&env)
             }
+            _ => Err(BdkJniError::Unsupported(
+                "Called `call_with_signer` with a request that doesn't take a signer".to_string(),
+            )),
         };
 
-        string_to_jstring(&env, &final_string).unwrap_or(JObject::null().into_inner())
+        write_response(&env, response_result)
     }
 }
 
@@ -0,0 +1,409 @@
+//! Sign and verify messages with the keys behind addresses, following BIP137 and BIP322
+
+use serde::{Deserialize, Serialize};
+
+use bdk::bitcoin::blockdata::opcodes;
+use bdk::bitcoin::blockdata::script::Builder;
+use bdk::bitcoin::consensus::encode::{deserialize, serialize};
+use bdk::bitcoin::hashes::{sha256, Hash, HashEngine};
+use bdk::bitcoin::secp256k1::recovery::{RecoverableSignature, RecoveryId};
+use bdk::bitcoin::secp256k1::{All, Message, Secp256k1};
+use bdk::bitcoin::util::bip32::ChildNumber;
+use bdk::bitcoin::util::misc::signed_msg_hash;
+use bdk::bitcoin::util::psbt::PartiallySignedTransaction;
+use bdk::bitcoin::{Address, OutPoint, PrivateKey, PublicKey, Script, Transaction, TxIn, TxOut};
+use bdk::blockchain::Blockchain;
+use bdk::database::{BatchDatabase, Database};
+use bdk::miniscript::descriptor::{DescriptorSecretKey, Wildcard};
+use bdk::{SignOptions, Wallet};
+
+use crate::{verify_script, BdkJniError};
+
+const BIP322_TAG: &[u8] = b"BIP0322-signed-message";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MessageFormat {
+    /// 65 bytes recoverable signature, with a header byte encoding the address type
+    Bip137,
+    /// The witness stack of the BIP322 `to_sign` transaction, only for segwit addresses
+    Bip322Simple,
+    /// The whole BIP322 `to_sign` transaction, proofs of funds with extra inputs can't be verified
+    Bip322Full,
+}
+
+/// BIP322 tagged hash of `message`
+fn message_hash(message: &str) -> sha256::Hash {
+    let tag = sha256::Hash::hash(BIP322_TAG);
+    let mut engine = sha256::Hash::engine();
+    engine.input(&tag[..]);
+    engine.input(&tag[..]);
+    engine.input(message.as_bytes());
+
+    sha256::Hash::from_engine(engine)
+}
+
+/// The BIP322 virtual transaction "funding" `script` with `message`
+fn to_spend(script: &Script, message: &str) -> Transaction {
+    Transaction {
+        version: 0,
+        lock_time: 0,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: Builder::new()
+                .push_int(0)
+                .push_slice(&message_hash(message)[..])
+                .into_script(),
+            sequence: 0,
+            witness: vec![],
+        }],
+        output: vec![TxOut {
+            value: 0,
+            script_pubkey: script.clone(),
+        }],
+    }
+}
+
+/// The unsigned BIP322 virtual transaction spending `to_spend`
+fn to_sign(to_spend: &Transaction) -> Transaction {
+    Transaction {
+        version: 0,
+        lock_time: 0,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(to_spend.txid(), 0),
+            script_sig: Script::new(),
+            sequence: 0,
+            witness: vec![],
+        }],
+        output: vec![TxOut {
+            value: 0,
+            script_pubkey: Builder::new()
+                .push_opcode(opcodes::all::OP_RETURN)
+                .into_script(),
+        }],
+    }
+}
+
+/// The single-key scripts of `pubkey`, segwit ones only for compressed keys
+fn pubkey_scripts(pubkey: &PublicKey, address: &Address) -> Vec<Script> {
+    let mut scripts = vec![Address::p2pkh(pubkey, address.network).script_pubkey()];
+    if let (Ok(p2wpkh), Ok(p2sh)) = (
+        Address::p2wpkh(pubkey, address.network),
+        Address::p2shwpkh(pubkey, address.network),
+    ) {
+        scripts.push(p2wpkh.script_pubkey());
+        scripts.push(p2sh.script_pubkey());
+    }
+
+    scripts
+}
+
+/// The BIP137 header byte for a key of `address`, before adding the recovery id
+fn bip137_header(address: &Address, compressed: bool) -> Result<u8, BdkJniError> {
+    let script = address.script_pubkey();
+    if script.is_p2pkh() {
+        Ok(if compressed { 31 } else { 27 })
+    } else if script.is_p2sh() {
+        Ok(35)
+    } else if script.is_v0_p2wpkh() {
+        Ok(39)
+    } else {
+        Err(BdkJniError::Unsupported(
+            "BIP137 signatures are only defined for single-key addresses, use BIP322".to_string(),
+        ))
+    }
+}
+
+/// The private key of the wallet behind `address`, derived from the wallet's signers
+fn address_key<B, D>(
+    wallet: &Wallet<B, D>,
+    address: &Address,
+    secp: &Secp256k1<All>,
+) -> Result<PrivateKey, BdkJniError>
+where
+    B: Blockchain,
+    D: BatchDatabase,
+{
+    let script = address.script_pubkey();
+    let (keychain, index) = wallet
+        .database()
+        .get_path_from_script_pubkey(&script)?
+        .ok_or_else(|| {
+            BdkJniError::Parsing(format!("Address {} is not part of the wallet", address))
+        })?;
+
+    for signer in wallet.get_signers(keychain).signers() {
+        let key = match signer.descriptor_secret_key() {
+            Some(DescriptorSecretKey::SinglePriv(single)) => single.key,
+            Some(DescriptorSecretKey::XPrv(xprv)) => {
+                let path = match xprv.wildcard {
+                    Wildcard::None => xprv.derivation_path.clone(),
+                    Wildcard::Unhardened => xprv
+                        .derivation_path
+                        .extend(&[ChildNumber::Normal { index }]),
+                    Wildcard::Hardened => xprv
+                        .derivation_path
+                        .extend(&[ChildNumber::Hardened { index }]),
+                };
+                xprv.xkey.derive_priv(secp, &path)?.private_key
+            }
+            None => continue,
+        };
+
+        if pubkey_scripts(&key.public_key(secp), address).contains(&script) {
+            return Ok(key);
+        }
+    }
+
+    Err(BdkJniError::Unsupported(format!(
+        "The wallet has no private key for {}, BIP137 needs a single-key address",
+        address
+    )))
+}
+
+/// Sign the BIP322 `to_sign` transaction of `address` with the wallet's signers
+fn sign_bip322<B, D>(
+    wallet: &Wallet<B, D>,
+    address: &Address,
+    message: &str,
+) -> Result<Transaction, BdkJniError>
+where
+    B: Blockchain,
+    D: BatchDatabase,
+{
+    let script = address.script_pubkey();
+    if !wallet.is_mine(&script)? {
+        return Err(BdkJniError::Parsing(format!(
+            "Address {} is not part of the wallet",
+            address
+        )));
+    }
+
+    let to_spend = to_spend(&script, message);
+    let mut psbt = PartiallySignedTransaction::from_unsigned_tx(to_sign(&to_spend))
+        .map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?;
+    psbt.inputs[0].witness_utxo = Some(to_spend.output[0].clone());
+    psbt.inputs[0].non_witness_utxo = Some(to_spend);
+
+    if !wallet.sign(&mut psbt, SignOptions::default())? {
+        return Err(BdkJniError::PsbtNotFinalized(vec![0]));
+    }
+
+    Ok(psbt.extract_tx())
+}
+
+/// Sign `message` with the key behind `address` and return the base64 signature
+pub(crate) fn sign<B, D>(
+    wallet: &Wallet<B, D>,
+    address: &Address,
+    message: &str,
+    format: MessageFormat,
+) -> Result<String, BdkJniError>
+where
+    B: Blockchain,
+    D: BatchDatabase,
+{
+    let signature = match format {
+        MessageFormat::Bip137 => {
+            let secp = Secp256k1::new();
+            let key = address_key(wallet, address, &secp)?;
+            let header = bip137_header(address, key.compressed)?;
+
+            let hash =
+                Message::from_slice(&signed_msg_hash(message)[..]).expect("Hashes are 32 bytes");
+            let (recovery_id, compact) = secp.sign_recoverable(&hash, &key.key).serialize_compact();
+
+            let mut signature = vec![header + recovery_id.to_i32() as u8];
+            signature.extend_from_slice(&compact);
+            signature
+        }
+        MessageFormat::Bip322Simple => {
+            let to_sign = sign_bip322(wallet, address, message)?;
+            if !to_sign.input[0].script_sig.is_empty() {
+                return Err(BdkJniError::Unsupported(
+                    "BIP322 simple signatures need a native segwit address, use `bip322_full`"
+                        .to_string(),
+                ));
+            }
+            serialize(&to_sign.input[0].witness)
+        }
+        MessageFormat::Bip322Full => serialize(&sign_bip322(wallet, address, message)?),
+    };
+
+    Ok(base64::encode(&signature))
+}
+
+/// Recover the key of a BIP137 signature and check that it controls `address`
+///
+/// The header byte is only used for the recovery id and key compression, since some wallets
+/// sign for segwit addresses with the P2PKH header.
+fn verify_bip137(address: &Address, message: &str, signature: &[u8]) -> bool {
+    let header = signature[0] - 27;
+    let recovered = RecoveryId::from_i32((header % 4) as i32)
+        .and_then(|id| RecoverableSignature::from_compact(&signature[1..], id))
+        .and_then(|signature| {
+            let hash =
+                Message::from_slice(&signed_msg_hash(message)[..]).expect("Hashes are 32 bytes");
+            Secp256k1::verification_only().recover(&hash, &signature)
+        });
+
+    match recovered {
+        Ok(key) => {
+            let pubkey = PublicKey {
+                compressed: header >= 4,
+                key,
+            };
+            pubkey_scripts(&pubkey, address).contains(&address.script_pubkey())
+        }
+        Err(_) => false,
+    }
+}
+
+/// Run the script of `address` against a BIP322 `to_sign` transaction
+///
+/// Proofs of funds, full signatures with more inputs than the virtual one, are not supported
+/// since verifying them needs the outputs they spend.
+fn verify_bip322(
+    address: &Address,
+    to_spend: &Transaction,
+    to_sign: &Transaction,
+) -> Result<bool, BdkJniError> {
+    let spends_to_spend = to_sign.input.first().map_or(false, |i| {
+        i.previous_output == OutPoint::new(to_spend.txid(), 0)
+    });
+    if spends_to_spend && to_sign.input.len() > 1 {
+        return Err(BdkJniError::Unsupported(
+            "BIP322 proofs of funds, with more than one input, can't be verified".to_string(),
+        ));
+    }
+
+    let structure_valid = spends_to_spend
+        && to_sign.input.len() == 1
+        && to_sign.output.len() == 1
+        && to_sign.output[0].value == 0
+        && to_sign.output[0].script_pubkey.is_op_return();
+    if !structure_valid {
+        return Ok(false);
+    }
+
+    verify_script(&address.script_pubkey(), 0, &serialize(to_sign), 0)
+}
+
+/// Verify a base64 `signature` of `message` for `address`, detecting its format
+pub(crate) fn verify(
+    address: &Address,
+    message: &str,
+    signature: &str,
+) -> Result<(bool, MessageFormat), BdkJniError> {
+    let signature =
+        base64::decode(signature.trim()).map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?;
+
+    if signature.len() == 65 && (27..=42).contains(&signature[0]) {
+        return Ok((
+            verify_bip137(address, message, &signature),
+            MessageFormat::Bip137,
+        ));
+    }
+
+    let to_spend = to_spend(&address.script_pubkey(), message);
+    if let Ok(witness) = deserialize::<Vec<Vec<u8>>>(&signature) {
+        let mut to_sign = to_sign(&to_spend);
+        to_sign.input[0].witness = witness;
+        Ok((
+            verify_bip322(address, &to_spend, &to_sign)?,
+            MessageFormat::Bip322Simple,
+        ))
+    } else if let Ok(to_sign) = deserialize::<Transaction>(&signature) {
+        Ok((
+            verify_bip322(address, &to_spend, &to_sign)?,
+            MessageFormat::Bip322Full,
+        ))
+    } else {
+        Err(BdkJniError::Parsing(
+            "Unknown signature format, expected BIP137 or BIP322".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::str::FromStr;
+
+    // From the README of bitcoinjs-message
+    const BIP137_ADDRESS: &str = "1F3sAm6ZtwLAUnj7d38pGFxtP3RVEvtsbV";
+    const BIP137_MESSAGE: &str = "This is an example of a signed message.";
+    const BIP137_SIGNATURE: &str =
+        "H9L5yLFjti0QTHhPyFrZCT1V/MMnBtXKmoiKDZ78NDBjERki6ZTQZdSMCtkgoNmp17By9ItJr8o7ChX0XxY91nk=";
+
+    #[test]
+    fn test_verify_bip137() {
+        let address = Address::from_str(BIP137_ADDRESS).unwrap();
+
+        assert_eq!(
+            verify(&address, BIP137_MESSAGE, BIP137_SIGNATURE).unwrap(),
+            (true, MessageFormat::Bip137)
+        );
+        assert_eq!(
+            verify(&address, "Another message", BIP137_SIGNATURE).unwrap(),
+            (false, MessageFormat::Bip137)
+        );
+    }
+
+    // From BIP322
+    const BIP322_ADDRESS: &str = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l";
+
+    #[test]
+    fn test_bip322_message_hash() {
+        assert_eq!(
+            message_hash("").to_string(),
+            "c90c269c4f8fcbe6880f72a721ddfbf1914268a794cbb21cfafee13770ae19f1"
+        );
+        assert_eq!(
+            message_hash("Hello World").to_string(),
+            "f0eb03b1a75ac6d9847f55c624a99169b5dccba2a31f5b23bea77ba270de0a7a"
+        );
+    }
+
+    #[test]
+    fn test_bip322_transactions() {
+        let script = Address::from_str(BIP322_ADDRESS).unwrap().script_pubkey();
+
+        let to_spend_empty = to_spend(&script, "");
+        assert_eq!(
+            to_spend_empty.txid().to_string(),
+            "c5680aa69bb8d860bf82d4e9cd3504b55dde018de765a91bb566283c545a99a7"
+        );
+        assert_eq!(
+            to_sign(&to_spend_empty).txid().to_string(),
+            "1e9654e951a5ba44c8604c4de6c67fd78a27e81dcadcfe1edf638ba3aaebaed6"
+        );
+
+        let to_spend_hello = to_spend(&script, "Hello World");
+        assert_eq!(
+            to_spend_hello.txid().to_string(),
+            "b79d196740ad5217771c1098fc4a4b51e0535c32236c71f1ea4d61a2d603352b"
+        );
+        assert_eq!(
+            to_sign(&to_spend_hello).txid().to_string(),
+            "88737ae86f2077145f93cc4b153ae9a1cb8d56afa511988c149c5c8c9d93bddf"
+        );
+    }
+
+    #[test]
+    fn test_verify_bip322_proof_of_funds() {
+        let address = Address::from_str(BIP137_ADDRESS).unwrap();
+        let mut to_sign = to_sign(&to_spend(&address.script_pubkey(), BIP137_MESSAGE));
+        to_sign.input.push(TxIn::default());
+
+        assert!(matches!(
+            verify(
+                &address,
+                BIP137_MESSAGE,
+                &base64::encode(&serialize(&to_sign))
+            ),
+            Err(BdkJniError::Unsupported(_))
+        ));
+    }
+}
@@ -0,0 +1,455 @@
+//! BIP39 helpers on top of the mnemonics supported by bdk
+
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+
+use bdk::bitcoin::hashes::{hex::FromHex, sha256, Hash};
+use bdk::keys::bip39::{Language, Mnemonic, MnemonicType};
+
+use crate::BdkJniError;
+
+/// BIP39 wordlists, mirrors `bip39::Language` so that it can be sent by the JVM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum WordlistLanguage {
+    English,
+    ChineseSimplified,
+    ChineseTraditional,
+    French,
+    Italian,
+    Japanese,
+    Korean,
+    Spanish,
+}
+
+impl WordlistLanguage {
+    pub(crate) const ALL: [WordlistLanguage; 8] = [
+        WordlistLanguage::English,
+        WordlistLanguage::ChineseSimplified,
+        WordlistLanguage::ChineseTraditional,
+        WordlistLanguage::French,
+        WordlistLanguage::Italian,
+        WordlistLanguage::Japanese,
+        WordlistLanguage::Korean,
+        WordlistLanguage::Spanish,
+    ];
+
+    /// Whether `word` is part of this wordlist
+    pub(crate) fn contains(self, word: &str) -> bool {
+        Language::from(self).wordmap().get_bits(word).is_ok()
+    }
+
+    /// All the words of this wordlist
+    pub(crate) fn words(self) -> &'static [&'static str] {
+        // Every word starts with the empty prefix
+        Language::from(self).wordlist().get_words_by_prefix("")
+    }
+}
+
+impl Default for WordlistLanguage {
+    fn default() -> Self {
+        WordlistLanguage::English
+    }
+}
+
+impl From<WordlistLanguage> for Language {
+    fn from(other: WordlistLanguage) -> Self {
+        match other {
+            WordlistLanguage::English => Language::English,
+            WordlistLanguage::ChineseSimplified => Language::ChineseSimplified,
+            WordlistLanguage::ChineseTraditional => Language::ChineseTraditional,
+            WordlistLanguage::French => Language::French,
+            WordlistLanguage::Italian => Language::Italian,
+            WordlistLanguage::Japanese => Language::Japanese,
+            WordlistLanguage::Korean => Language::Korean,
+            WordlistLanguage::Spanish => Language::Spanish,
+        }
+    }
+}
+
+/// Map a word count to a mnemonic type, only the sizes defined by BIP39 are accepted
+pub(crate) fn mnemonic_type(word_count: usize) -> Result<MnemonicType, BdkJniError> {
+    MnemonicType::for_word_count(word_count).map_err(|_| {
+        BdkJniError::Mnemonic(format!(
+            "Invalid word count {}, must be one of 12, 15, 18, 21 or 24",
+            word_count
+        ))
+    })
+}
+
+/// How the entropy provided by the caller is encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum EntropySource {
+    /// Raw entropy in hex, of the exact size needed by the word count
+    Hex,
+    /// Dice rolls, as a string of digits between 1 and 6
+    Dice,
+    /// Coin flips, as a string of 0 and 1
+    Coin,
+}
+
+impl EntropySource {
+    /// Entropy carried by each roll or flip, in bits
+    fn bits_per_symbol(self) -> f64 {
+        match self {
+            EntropySource::Hex => 4.0,
+            EntropySource::Dice => 6f64.log2(),
+            EntropySource::Coin => 1.0,
+        }
+    }
+
+    fn is_valid_symbol(self, c: char) -> bool {
+        match self {
+            EntropySource::Hex => c.is_ascii_hexdigit(),
+            EntropySource::Dice => ('1'..='6').contains(&c),
+            EntropySource::Coin => c == '0' || c == '1',
+        }
+    }
+}
+
+/// Turn the entropy provided by the caller in the `len` bytes needed for a mnemonic
+///
+/// Hex entropy is used as-is, dice rolls and coin flips are hashed with SHA256. Unless
+/// `mix_system_entropy` is set the rolls or flips must carry at least `len * 8` bits, when it is
+/// set the result is XORed with the OS random number generator so that it can't be weaker than
+/// a randomly generated mnemonic.
+pub(crate) fn entropy_from_user(
+    entropy: &str,
+    source: EntropySource,
+    len: usize,
+    mix_system_entropy: bool,
+) -> Result<Vec<u8>, BdkJniError> {
+    let symbols = entropy
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>();
+    if let Some(c) = symbols.chars().find(|c| !source.is_valid_symbol(*c)) {
+        return Err(BdkJniError::Mnemonic(format!(
+            "Invalid character `{}` in {:?} entropy",
+            c, source
+        )));
+    }
+
+    let mut bytes = match source {
+        EntropySource::Hex => {
+            let bytes = Vec::<u8>::from_hex(&symbols)
+                .map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?;
+            if bytes.len() != len {
+                return Err(BdkJniError::Mnemonic(format!(
+                    "Expected {} bytes of entropy, got {}",
+                    len,
+                    bytes.len()
+                )));
+            }
+            bytes
+        }
+        _ => {
+            let bits = symbols.len() as f64 * source.bits_per_symbol();
+            if !mix_system_entropy && bits < (len * 8) as f64 {
+                let needed = ((len * 8) as f64 / source.bits_per_symbol()).ceil();
+                return Err(BdkJniError::Mnemonic(format!(
+                    "Not enough entropy: {} {:?} symbols provided, at least {} needed",
+                    symbols.len(),
+                    source,
+                    needed
+                )));
+            }
+            sha256::Hash::hash(symbols.as_bytes())[..len].to_vec()
+        }
+    };
+
+    if mix_system_entropy {
+        let mut random = vec![0; len];
+        rand::thread_rng().fill_bytes(&mut random);
+        bytes.iter_mut().zip(random).for_each(|(b, r)| *b ^= r);
+    }
+
+    Ok(bytes)
+}
+
+/// A word of a phrase that is not part of the wordlist
+#[derive(Debug, Serialize)]
+pub(crate) struct InvalidWord {
+    /// Position of the word in the phrase, starting from 0
+    pub(crate) index: usize,
+    pub(crate) word: String,
+    /// Words of the wordlist starting with the first letters of `word`
+    pub(crate) suggestions: Vec<String>,
+}
+
+/// Diagnostics about a mnemonic phrase, reported without failing so that they can be shown to the
+/// user
+#[derive(Debug, Serialize)]
+pub(crate) struct MnemonicValidation {
+    pub(crate) valid: bool,
+    /// Language the phrase has been checked against, either the requested one or the wordlist
+    /// containing most of its words
+    pub(crate) language: Option<WordlistLanguage>,
+    pub(crate) word_count: usize,
+    pub(crate) word_count_valid: bool,
+    pub(crate) invalid_words: Vec<InvalidWord>,
+    /// `None` when the checksum can't be verified because of invalid words or word count
+    pub(crate) checksum_valid: Option<bool>,
+}
+
+/// Maximum number of suggestions returned for each invalid word
+const MAX_SUGGESTIONS: usize = 10;
+
+/// The wordlist containing most of `words`, ties are broken in the order of
+/// [`WordlistLanguage::ALL`]
+pub(crate) fn detect_language(words: &[&str]) -> Option<WordlistLanguage> {
+    let mut best = None;
+    let mut best_count = 0;
+    for language in WordlistLanguage::ALL.iter() {
+        let count = words.iter().filter(|w| language.contains(w)).count();
+        if count > best_count {
+            best = Some(*language);
+            best_count = count;
+        }
+    }
+
+    best
+}
+
+/// Words of `language` starting with the longest prefix of `word` that matches anything
+///
+/// Not all the wordlists are sorted, so they are scanned instead of using a binary search.
+fn suggestions(language: WordlistLanguage, word: &str) -> Vec<String> {
+    let chars = word.char_indices().map(|(i, _)| i).skip(1);
+    let prefixes = chars.chain(std::iter::once(word.len())).rev();
+    for end in prefixes {
+        let matches = language
+            .words()
+            .iter()
+            .filter(|w| w.starts_with(&word[..end]))
+            .take(MAX_SUGGESTIONS)
+            .map(|w| w.to_string())
+            .collect::<Vec<_>>();
+        if !matches.is_empty() {
+            return matches;
+        }
+    }
+
+    vec![]
+}
+
+/// Lowercase NFKD form of a phrase, the one used by the wordlists
+fn normalize(phrase: &str) -> String {
+    phrase.nfkd().collect::<String>().to_lowercase()
+}
+
+/// Parse a mnemonic phrase the same way `validate` checks it: normalized, with the words
+/// separated by single spaces
+pub(crate) fn from_phrase(phrase: &str, language: Language) -> Result<Mnemonic, BdkJniError> {
+    let phrase = normalize(phrase)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    Mnemonic::from_phrase(&phrase, language).map_err(|e| BdkJniError::Mnemonic(e.to_string()))
+}
+
+/// Check a mnemonic phrase word by word, then its length and checksum
+pub(crate) fn validate(phrase: &str, language: Option<WordlistLanguage>) -> MnemonicValidation {
+    let phrase = normalize(phrase);
+    let words = phrase.split_whitespace().collect::<Vec<_>>();
+    let language = language.or_else(|| detect_language(&words));
+
+    let invalid_words = match language {
+        Some(language) => words
+            .iter()
+            .enumerate()
+            .filter(|(_, word)| !language.contains(word))
+            .map(|(index, word)| InvalidWord {
+                index,
+                word: word.to_string(),
+                suggestions: suggestions(language, word),
+            })
+            .collect(),
+        None => words
+            .iter()
+            .enumerate()
+            .map(|(index, word)| InvalidWord {
+                index,
+                word: word.to_string(),
+                suggestions: vec![],
+            })
+            .collect::<Vec<_>>(),
+    };
+    let word_count_valid = MnemonicType::for_word_count(words.len()).is_ok();
+
+    let checksum_valid = match language {
+        Some(language) if invalid_words.is_empty() && word_count_valid => {
+            Some(Mnemonic::validate(&words.join(" "), language.into()).is_ok())
+        }
+        _ => None,
+    };
+
+    MnemonicValidation {
+        valid: checksum_valid == Some(true),
+        language,
+        word_count: words.len(),
+        word_count_valid,
+        invalid_words,
+        checksum_valid,
+    }
+}
+
+/// All the words that complete `phrase` into a mnemonic with a valid checksum
+///
+/// `phrase` must have one word less than a valid mnemonic, its last word only carries some bits
+/// of entropy and the checksum.
+pub(crate) fn final_words(
+    phrase: &str,
+    language: WordlistLanguage,
+) -> Result<Vec<String>, BdkJniError> {
+    let phrase = normalize(phrase);
+    let words = phrase.split_whitespace().collect::<Vec<_>>();
+
+    mnemonic_type(words.len() + 1)?;
+    let invalid = words
+        .iter()
+        .filter(|w| !language.contains(w))
+        .collect::<Vec<_>>();
+    if !invalid.is_empty() {
+        return Err(BdkJniError::Mnemonic(format!(
+            "Words not in the {:?} wordlist: {:?}",
+            language, invalid
+        )));
+    }
+
+    let phrase = words.join(" ");
+    Ok(language
+        .words()
+        .iter()
+        .filter(|last| {
+            let candidate = format!("{} {}", phrase, last);
+            Mnemonic::validate(&candidate, language.into()).is_ok()
+        })
+        .map(|last| last.to_string())
+        .collect())
+}
+
+/// XOR the entropy of `parts` together
+fn xor_entropy(parts: &[Mnemonic]) -> Result<Vec<u8>, BdkJniError> {
+    let mut entropy = parts
+        .first()
+        .ok_or_else(|| BdkJniError::Mnemonic("No parts to combine".to_string()))?
+        .entropy()
+        .to_vec();
+
+    for part in &parts[1..] {
+        if part.entropy().len() != entropy.len() {
+            return Err(BdkJniError::Mnemonic(
+                "All the parts must have the same number of words".to_string(),
+            ));
+        }
+        entropy
+            .iter_mut()
+            .zip(part.entropy())
+            .for_each(|(e, p)| *e ^= p);
+    }
+
+    Ok(entropy)
+}
+
+/// Split a mnemonic in `count` mnemonics of the same length whose entropy XORs back to the
+/// original one, compatible with Coldcard's Seed XOR
+///
+/// All the parts but the last one are random, every part is a valid BIP39 mnemonic on its own.
+pub(crate) fn xor_split(mnemonic: &Mnemonic, count: usize) -> Result<Vec<Mnemonic>, BdkJniError> {
+    if count < 2 {
+        return Err(BdkJniError::Mnemonic(format!(
+            "Invalid number of parts {}, must be at least 2",
+            count
+        )));
+    }
+
+    let language = mnemonic.language();
+    let mut parts = (1..count)
+        .map(|_| {
+            let mut entropy = vec![0; mnemonic.entropy().len()];
+            rand::thread_rng().fill_bytes(&mut entropy);
+            Mnemonic::from_entropy(&entropy, language)
+                .map_err(|e| BdkJniError::Mnemonic(e.to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut all = parts.clone();
+    all.push(mnemonic.clone());
+    let last = xor_entropy(&all)?;
+    parts.push(
+        Mnemonic::from_entropy(&last, language)
+            .map_err(|e| BdkJniError::Mnemonic(e.to_string()))?,
+    );
+
+    Ok(parts)
+}
+
+/// Recombine the parts of a Seed XOR backup
+pub(crate) fn xor_combine(parts: &[Mnemonic]) -> Result<Mnemonic, BdkJniError> {
+    let language = parts
+        .first()
+        .map(|p| p.language())
+        .unwrap_or(Language::English);
+
+    Mnemonic::from_entropy(&xor_entropy(parts)?, language)
+        .map_err(|e| BdkJniError::Mnemonic(e.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_contains() {
+        // Most wordlists aren't sorted, every word must be found anyway
+        for language in WordlistLanguage::ALL.iter() {
+            assert_eq!(language.words().len(), 2048);
+            assert!(language.words().iter().all(|w| language.contains(w)));
+        }
+        assert!(!WordlistLanguage::English.contains("abaco"));
+        assert!(!WordlistLanguage::Spanish.contains("abandon"));
+    }
+
+    #[test]
+    fn test_validate_normalization() {
+        // "ábaco" with a precomposed "á", while the wordlist is in NFKD
+        let phrase = "\u{e1}baco ".repeat(11) + "\u{c1}BACO";
+        let validation = validate(&phrase, None);
+        assert_eq!(validation.language, Some(WordlistLanguage::Spanish));
+        assert!(validation.invalid_words.is_empty());
+        assert_eq!(validation.checksum_valid, Some(false));
+
+        let validation = validate(&"abandon ".repeat(11), None);
+        assert_eq!(validation.language, Some(WordlistLanguage::English));
+        assert!(!validation.word_count_valid);
+    }
+
+    #[test]
+    fn test_from_phrase_normalization() {
+        let phrase = "Abandon ".repeat(11) + " ABOUT\n";
+        assert!(validate(&phrase, None).valid);
+
+        let mnemonic = from_phrase(&phrase, Language::English).unwrap();
+        assert_eq!(mnemonic.to_string(), "abandon ".repeat(11) + "about");
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let validation = validate("abandon abandonn zzz", Some(WordlistLanguage::English));
+        assert_eq!(validation.invalid_words.len(), 2);
+        assert_eq!(validation.invalid_words[0].suggestions, vec!["abandon"]);
+        assert_eq!(validation.invalid_words[1].word, "zzz");
+        assert_eq!(
+            validation.invalid_words[1].suggestions,
+            vec!["zebra", "zero", "zone", "zoo"]
+        );
+
+        // The Spanish wordlist is not sorted bytewise because of the accents
+        let suggestions = suggestions(WordlistLanguage::Spanish, "a\u{301}ba");
+        assert!(suggestions.contains(&"a\u{301}baco".to_string()));
+    }
+}
@@ -0,0 +1,200 @@
+//! Sorted multisig descriptors, with our own key derived at its BIP48 path
+
+use serde::{Deserialize, Serialize};
+
+use bdk::bitcoin::secp256k1::Secp256k1;
+use bdk::bitcoin::util::bip32::{ChildNumber, DerivationPath, ExtendedPrivKey, ExtendedPubKey};
+use bdk::bitcoin::Network;
+use bdk::descriptor::IntoWalletDescriptor;
+use bdk::KeychainKind;
+
+use crate::{descriptor_with_checksum, format_key_origin, slip132, BdkJniError};
+
+/// Maximum number of keys in a `sortedmulti()` inside P2WSH
+const MAX_KEYS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MultisigScriptType {
+    /// `wsh(sortedmulti())`
+    P2wsh,
+    /// `sh(wsh(sortedmulti()))`
+    P2shP2wsh,
+}
+
+impl MultisigScriptType {
+    /// Last hardened step of the BIP48 derivation path
+    fn bip48_script(self) -> u32 {
+        match self {
+            MultisigScriptType::P2shP2wsh => 1,
+            MultisigScriptType::P2wsh => 2,
+        }
+    }
+
+    fn wrap(self, sortedmulti: &str) -> String {
+        match self {
+            MultisigScriptType::P2wsh => format!("wsh({})", sortedmulti),
+            MultisigScriptType::P2shP2wsh => format!("sh(wsh({}))", sortedmulti),
+        }
+    }
+}
+
+/// `m/48'/coin'/account'/script'`
+pub(crate) fn bip48_path(
+    script_type: MultisigScriptType,
+    network: Network,
+    account: u32,
+) -> DerivationPath {
+    let coin_type = match network {
+        Network::Bitcoin => 0,
+        _ => 1,
+    };
+
+    vec![
+        ChildNumber::Hardened { index: 48 },
+        ChildNumber::Hardened { index: coin_type },
+        ChildNumber::Hardened { index: account },
+        ChildNumber::Hardened {
+            index: script_type.bip48_script(),
+        },
+    ]
+    .into()
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct MultisigDescriptors {
+    /// Including the checksum, with our own key as an xprv if we have one
+    pub(crate) descriptor: String,
+    pub(crate) change_descriptor: String,
+    pub(crate) checksum: String,
+    pub(crate) change_checksum: String,
+    /// Including the checksum, with every key as an xpub, to be shared with the cosigners
+    pub(crate) public_descriptor: String,
+    pub(crate) public_change_descriptor: String,
+    pub(crate) public_checksum: String,
+    pub(crate) public_change_checksum: String,
+    /// Our own key as `[fingerprint/path]xpub`, to be shared with the cosigners
+    pub(crate) key_origin: Option<String>,
+}
+
+/// The private and public descriptor of one keychain, each with its checksum
+struct KeychainDescriptor {
+    descriptor: (String, String),
+    public_descriptor: (String, String),
+}
+
+/// Build, validate and checksum the descriptor of one keychain
+fn keychain_descriptor(
+    threshold: usize,
+    script_type: MultisigScriptType,
+    keys: &[String],
+    keychain: KeychainKind,
+    network: Network,
+) -> Result<KeychainDescriptor, BdkJniError> {
+    let keys = keys
+        .iter()
+        .map(|key| format!("{}/{}/*", key, keychain as u32))
+        .collect::<Vec<_>>();
+    let sortedmulti = format!("sortedmulti({},{})", threshold, keys.join(","));
+
+    let secp = Secp256k1::new();
+    let (descriptor, keymap) = script_type
+        .wrap(&sortedmulti)
+        .as_str()
+        .into_wallet_descriptor(&secp, network)
+        .map_err(bdk::Error::from)?;
+
+    Ok(KeychainDescriptor {
+        descriptor: descriptor_with_checksum(&descriptor.to_string_with_secret(&keymap))?,
+        public_descriptor: descriptor_with_checksum(&descriptor.to_string())?,
+    })
+}
+
+/// The external and internal descriptors of a `threshold`-of-n multisig
+///
+/// `cosigners` are account-level xpubs or their SLIP-132 equivalent, optionally prefixed by their
+/// `[fingerprint/path]` origin. When missing, `script_type` is inferred from Ypub and Zpub
+/// cosigners. Our own key, if any, is derived from `master` at its BIP48 path.
+pub(crate) fn descriptors(
+    threshold: usize,
+    script_type: Option<MultisigScriptType>,
+    cosigners: &[String],
+    master: Option<ExtendedPrivKey>,
+    account: u32,
+    network: Network,
+) -> Result<MultisigDescriptors, BdkJniError> {
+    let secp = Secp256k1::new();
+
+    let script_type = script_type
+        .or_else(|| {
+            cosigners.iter().find_map(|key| {
+                let key = key.trim();
+                let key = key.rsplit(']').next().unwrap_or(key);
+                slip132::decode(key).ok()?.version.multisig_script_type()
+            })
+        })
+        .ok_or_else(|| {
+            BdkJniError::Parsing(
+                "`script_type` is required unless the cosigners are Ypub or Zpub".to_string(),
+            )
+        })?;
+
+    let mut keys = cosigners
+        .iter()
+        .map(|key| slip132::normalize_descriptor(key.trim()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let key_origin = match master {
+        Some(master) => {
+            let path = bip48_path(script_type, network, account);
+            let xprv = master.derive_priv(&secp, &path)?;
+            let origin = format_key_origin(master.fingerprint(&secp), &path);
+            let xpub = ExtendedPubKey::from_private(&secp, &xprv);
+
+            keys.push(format!("{}{}", origin, xprv));
+            Some(format!("{}{}", origin, xpub))
+        }
+        None => None,
+    };
+
+    if keys.is_empty() || keys.len() > MAX_KEYS {
+        return Err(BdkJniError::Parsing(format!(
+            "Invalid number of keys {}, must be between 1 and {}",
+            keys.len(),
+            MAX_KEYS
+        )));
+    }
+    if threshold == 0 || threshold > keys.len() {
+        return Err(BdkJniError::Parsing(format!(
+            "Invalid threshold {} for {} keys",
+            threshold,
+            keys.len()
+        )));
+    }
+
+    let external = keychain_descriptor(
+        threshold,
+        script_type,
+        &keys,
+        KeychainKind::External,
+        network,
+    )?;
+    let internal = keychain_descriptor(
+        threshold,
+        script_type,
+        &keys,
+        KeychainKind::Internal,
+        network,
+    )?;
+
+    Ok(MultisigDescriptors {
+        descriptor: external.descriptor.0,
+        change_descriptor: internal.descriptor.0,
+        checksum: external.descriptor.1,
+        change_checksum: internal.descriptor.1,
+        public_descriptor: external.public_descriptor.0,
+        public_change_descriptor: internal.public_descriptor.0,
+        public_checksum: external.public_descriptor.1,
+        public_change_checksum: internal.public_descriptor.1,
+        key_origin,
+    })
+}
@@ -0,0 +1,696 @@
+//! Conversion between version 0 (BIP174) and version 2 (BIP370) PSBTs
+//!
+//! `rust-bitcoin` only understands version 0, so version 2 PSBTs are converted at the key-value
+//! map level: the fields that moved out of the unsigned transaction are translated and every
+//! other field is carried over untouched.
+
+use std::convert::TryFrom;
+use std::io::Cursor;
+
+use bdk::bitcoin::consensus::encode::{deserialize, serialize, Decodable, VarInt};
+use bdk::bitcoin::{OutPoint, Script, Transaction, TxIn, TxOut, Txid};
+
+use crate::{BdkJniError, PSBT_MAGIC};
+
+const PSBT_GLOBAL_UNSIGNED_TX: u8 = 0x00;
+const PSBT_GLOBAL_TX_VERSION: u8 = 0x02;
+const PSBT_GLOBAL_FALLBACK_LOCKTIME: u8 = 0x03;
+const PSBT_GLOBAL_INPUT_COUNT: u8 = 0x04;
+const PSBT_GLOBAL_OUTPUT_COUNT: u8 = 0x05;
+const PSBT_GLOBAL_TX_MODIFIABLE: u8 = 0x06;
+const PSBT_GLOBAL_VERSION: u8 = 0xfb;
+
+const PSBT_IN_PREVIOUS_TXID: u8 = 0x0e;
+const PSBT_IN_OUTPUT_INDEX: u8 = 0x0f;
+const PSBT_IN_SEQUENCE: u8 = 0x10;
+const PSBT_IN_REQUIRED_TIME_LOCKTIME: u8 = 0x11;
+const PSBT_IN_REQUIRED_HEIGHT_LOCKTIME: u8 = 0x12;
+
+const PSBT_OUT_AMOUNT: u8 = 0x03;
+const PSBT_OUT_SCRIPT: u8 = 0x04;
+
+/// Fields only allowed in version 2 PSBTs
+const V2_GLOBAL_FIELDS: &[u8] = &[
+    PSBT_GLOBAL_TX_VERSION,
+    PSBT_GLOBAL_FALLBACK_LOCKTIME,
+    PSBT_GLOBAL_INPUT_COUNT,
+    PSBT_GLOBAL_OUTPUT_COUNT,
+    PSBT_GLOBAL_TX_MODIFIABLE,
+];
+const V2_INPUT_FIELDS: &[u8] = &[
+    PSBT_IN_PREVIOUS_TXID,
+    PSBT_IN_OUTPUT_INDEX,
+    PSBT_IN_SEQUENCE,
+    PSBT_IN_REQUIRED_TIME_LOCKTIME,
+    PSBT_IN_REQUIRED_HEIGHT_LOCKTIME,
+];
+const V2_OUTPUT_FIELDS: &[u8] = &[PSBT_OUT_AMOUNT, PSBT_OUT_SCRIPT];
+
+/// Locktimes below this value are block heights, the others are timestamps
+const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+/// A key-value map, keys include their type byte
+type Map = Vec<(Vec<u8>, Vec<u8>)>;
+
+struct RawPsbt {
+    global: Map,
+    inputs: Vec<Map>,
+    outputs: Vec<Map>,
+}
+
+fn invalid(msg: &str) -> BdkJniError {
+    BdkJniError::Parsing(format!("Invalid PSBT: {}", msg))
+}
+
+fn get<'m>(map: &'m Map, key_type: u8) -> Option<&'m [u8]> {
+    map.iter()
+        .find(|(key, _)| key.as_slice() == [key_type])
+        .map(|(_, value)| value.as_slice())
+}
+
+fn get_u32(map: &Map, key_type: u8) -> Result<Option<u32>, BdkJniError> {
+    get(map, key_type)
+        .map(|value| deserialize(value).map_err(|_| invalid("malformed u32 field")))
+        .transpose()
+}
+
+fn get_count(map: &Map, key_type: u8) -> Result<usize, BdkJniError> {
+    let value = get(map, key_type).ok_or_else(|| invalid("missing input or output count"))?;
+    let count: VarInt = deserialize(value).map_err(|_| invalid("malformed count"))?;
+    Ok(count.0 as usize)
+}
+
+fn has_any(map: &Map, key_types: &[u8]) -> bool {
+    map.iter()
+        .any(|(key, _)| key.len() == 1 && key_types.contains(&key[0]))
+}
+
+/// Drop the fields with the given types, keeping every other one
+fn without(map: Map, key_types: &[u8]) -> Map {
+    map.into_iter()
+        .filter(|(key, _)| !(key.len() == 1 && key_types.contains(&key[0])))
+        .collect()
+}
+
+fn with(mut map: Map, fields: Vec<(u8, Vec<u8>)>) -> Map {
+    map.extend(
+        fields
+            .into_iter()
+            .map(|(key_type, value)| (vec![key_type], value)),
+    );
+    map.sort();
+    map
+}
+
+fn read_bytes(cursor: &mut Cursor<&[u8]>) -> Result<Vec<u8>, BdkJniError> {
+    let len = VarInt::consensus_decode(&mut *cursor).map_err(|_| invalid("truncated"))?;
+    let start = cursor.position() as usize;
+    let end = start
+        .checked_add(len.0 as usize)
+        .filter(|end| *end <= cursor.get_ref().len())
+        .ok_or_else(|| invalid("truncated"))?;
+    cursor.set_position(end as u64);
+
+    Ok(cursor.get_ref()[start..end].to_vec())
+}
+
+fn read_map(cursor: &mut Cursor<&[u8]>) -> Result<Map, BdkJniError> {
+    let mut map = Map::new();
+    loop {
+        let key = read_bytes(cursor)?;
+        if key.is_empty() {
+            return Ok(map);
+        }
+        let value = read_bytes(cursor)?;
+        map.push((key, value));
+    }
+}
+
+fn write_map(map: &[(Vec<u8>, Vec<u8>)], out: &mut Vec<u8>) {
+    for (key, value) in map {
+        out.extend(serialize(&VarInt(key.len() as u64)));
+        out.extend(key);
+        out.extend(serialize(&VarInt(value.len() as u64)));
+        out.extend(value);
+    }
+    out.push(0x00);
+}
+
+impl RawPsbt {
+    fn parse(bytes: &[u8]) -> Result<Self, BdkJniError> {
+        if !bytes.starts_with(PSBT_MAGIC) {
+            return Err(invalid("bad magic"));
+        }
+        let mut cursor = Cursor::new(bytes);
+        cursor.set_position(PSBT_MAGIC.len() as u64);
+
+        let global = read_map(&mut cursor)?;
+        let version = get_u32(&global, PSBT_GLOBAL_VERSION)?.unwrap_or(0);
+        let (input_count, output_count) = match (version, get(&global, PSBT_GLOBAL_UNSIGNED_TX)) {
+            (0, Some(tx)) => {
+                let tx: Transaction =
+                    deserialize(tx).map_err(|_| invalid("malformed unsigned tx"))?;
+                (tx.input.len(), tx.output.len())
+            }
+            (0, None) => return Err(invalid("missing unsigned tx")),
+            (2, None) => (
+                get_count(&global, PSBT_GLOBAL_INPUT_COUNT)?,
+                get_count(&global, PSBT_GLOBAL_OUTPUT_COUNT)?,
+            ),
+            (2, Some(_)) => return Err(invalid("unsigned tx in a version 2 PSBT")),
+            (v, _) => return Err(BdkJniError::Unsupported(format!("PSBT version {}", v))),
+        };
+
+        let inputs = (0..input_count)
+            .map(|_| read_map(&mut cursor))
+            .collect::<Result<Vec<_>, _>>()?;
+        let outputs = (0..output_count)
+            .map(|_| read_map(&mut cursor))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let raw = RawPsbt {
+            global,
+            inputs,
+            outputs,
+        };
+        raw.check_fields(version)?;
+
+        Ok(raw)
+    }
+
+    /// Check the fields that depend on the version, as described in BIP370
+    fn check_fields(&self, version: u32) -> Result<(), BdkJniError> {
+        if version == 0 {
+            if has_any(&self.global, V2_GLOBAL_FIELDS)
+                || self.inputs.iter().any(|i| has_any(i, V2_INPUT_FIELDS))
+                || self.outputs.iter().any(|o| has_any(o, V2_OUTPUT_FIELDS))
+            {
+                return Err(invalid("version 2 field in a version 0 PSBT"));
+            }
+            return Ok(());
+        }
+
+        for input in &self.inputs {
+            if get_u32(input, PSBT_IN_REQUIRED_TIME_LOCKTIME)?
+                .map_or(false, |time| time < LOCKTIME_THRESHOLD)
+            {
+                return Err(invalid("required time locktime is a height"));
+            }
+            if get_u32(input, PSBT_IN_REQUIRED_HEIGHT_LOCKTIME)?
+                .map_or(false, |height| height >= LOCKTIME_THRESHOLD)
+            {
+                return Err(invalid("required height locktime is a time"));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut out = PSBT_MAGIC.to_vec();
+        write_map(&self.global, &mut out);
+        for map in self.inputs.iter().chain(self.outputs.iter()) {
+            write_map(map, &mut out);
+        }
+        out
+    }
+
+    fn version(&self) -> Result<u32, BdkJniError> {
+        Ok(get_u32(&self.global, PSBT_GLOBAL_VERSION)?.unwrap_or(0))
+    }
+
+    /// Pick the locktime of a version 2 PSBT, as described in BIP370
+    fn locktime(&self) -> Result<u32, BdkJniError> {
+        let mut requirements = Vec::new();
+        for input in &self.inputs {
+            let time = get_u32(input, PSBT_IN_REQUIRED_TIME_LOCKTIME)?;
+            let height = get_u32(input, PSBT_IN_REQUIRED_HEIGHT_LOCKTIME)?;
+            if time.is_some() || height.is_some() {
+                requirements.push((time, height));
+            }
+        }
+
+        if requirements.is_empty() {
+            return Ok(get_u32(&self.global, PSBT_GLOBAL_FALLBACK_LOCKTIME)?.unwrap_or(0));
+        }
+        if requirements.iter().all(|(_, height)| height.is_some()) {
+            return Ok(requirements.iter().filter_map(|(_, h)| *h).max().unwrap());
+        }
+        if requirements.iter().all(|(time, _)| time.is_some()) {
+            return Ok(requirements.iter().filter_map(|(t, _)| *t).max().unwrap());
+        }
+
+        Err(invalid("inputs require incompatible locktime types"))
+    }
+
+    fn into_v0(self) -> Result<Self, BdkJniError> {
+        let version = get_u32(&self.global, PSBT_GLOBAL_TX_VERSION)?
+            .ok_or_else(|| invalid("missing tx version"))?;
+        let lock_time = self.locktime()?;
+
+        let input = self
+            .inputs
+            .iter()
+            .map(|input| {
+                let txid: Txid = get(input, PSBT_IN_PREVIOUS_TXID)
+                    .map(deserialize)
+                    .transpose()
+                    .map_err(|_| invalid("malformed previous txid"))?
+                    .ok_or_else(|| invalid("missing previous txid"))?;
+                let vout = get_u32(input, PSBT_IN_OUTPUT_INDEX)?
+                    .ok_or_else(|| invalid("missing output index"))?;
+
+                Ok(TxIn {
+                    previous_output: OutPoint::new(txid, vout),
+                    script_sig: Script::new(),
+                    sequence: get_u32(input, PSBT_IN_SEQUENCE)?.unwrap_or(0xffff_ffff),
+                    witness: vec![],
+                })
+            })
+            .collect::<Result<Vec<_>, BdkJniError>>()?;
+        let output = self
+            .outputs
+            .iter()
+            .map(|output| {
+                let amount: i64 = get(output, PSBT_OUT_AMOUNT)
+                    .map(deserialize)
+                    .transpose()
+                    .map_err(|_| invalid("malformed amount"))?
+                    .ok_or_else(|| invalid("missing amount"))?;
+                let value = u64::try_from(amount).map_err(|_| invalid("malformed amount"))?;
+                let script_pubkey = Script::from(
+                    get(output, PSBT_OUT_SCRIPT)
+                        .ok_or_else(|| invalid("missing output script"))?
+                        .to_vec(),
+                );
+
+                Ok(TxOut {
+                    value,
+                    script_pubkey,
+                })
+            })
+            .collect::<Result<Vec<_>, BdkJniError>>()?;
+
+        let tx = Transaction {
+            version: version as i32,
+            lock_time,
+            input,
+            output,
+        };
+
+        Ok(RawPsbt {
+            global: with(
+                without(
+                    without(self.global, V2_GLOBAL_FIELDS),
+                    &[PSBT_GLOBAL_VERSION],
+                ),
+                vec![(PSBT_GLOBAL_UNSIGNED_TX, serialize(&tx))],
+            ),
+            inputs: self
+                .inputs
+                .into_iter()
+                .map(|input| without(input, V2_INPUT_FIELDS))
+                .collect(),
+            outputs: self
+                .outputs
+                .into_iter()
+                .map(|output| without(output, V2_OUTPUT_FIELDS))
+                .collect(),
+        })
+    }
+
+    fn into_v2(self) -> Result<Self, BdkJniError> {
+        let tx: Transaction = get(&self.global, PSBT_GLOBAL_UNSIGNED_TX)
+            .map(deserialize)
+            .transpose()
+            .map_err(|_| invalid("malformed unsigned tx"))?
+            .ok_or_else(|| invalid("missing unsigned tx"))?;
+
+        Ok(RawPsbt {
+            global: with(
+                without(self.global, &[PSBT_GLOBAL_UNSIGNED_TX, PSBT_GLOBAL_VERSION]),
+                vec![
+                    (PSBT_GLOBAL_TX_VERSION, serialize(&(tx.version as u32))),
+                    (PSBT_GLOBAL_FALLBACK_LOCKTIME, serialize(&tx.lock_time)),
+                    (
+                        PSBT_GLOBAL_INPUT_COUNT,
+                        serialize(&VarInt(tx.input.len() as u64)),
+                    ),
+                    (
+                        PSBT_GLOBAL_OUTPUT_COUNT,
+                        serialize(&VarInt(tx.output.len() as u64)),
+                    ),
+                    (PSBT_GLOBAL_VERSION, serialize(&2u32)),
+                ],
+            ),
+            inputs: self
+                .inputs
+                .into_iter()
+                .zip(tx.input.iter())
+                .map(|(input, txin)| {
+                    with(
+                        input,
+                        vec![
+                            (PSBT_IN_PREVIOUS_TXID, serialize(&txin.previous_output.txid)),
+                            (PSBT_IN_OUTPUT_INDEX, serialize(&txin.previous_output.vout)),
+                            (PSBT_IN_SEQUENCE, serialize(&txin.sequence)),
+                        ],
+                    )
+                })
+                .collect(),
+            outputs: self
+                .outputs
+                .into_iter()
+                .zip(tx.output.iter())
+                .map(|(output, txout)| {
+                    with(
+                        output,
+                        vec![
+                            (PSBT_OUT_AMOUNT, serialize(&(txout.value as i64))),
+                            (PSBT_OUT_SCRIPT, txout.script_pubkey.to_bytes()),
+                        ],
+                    )
+                })
+                .collect(),
+        })
+    }
+}
+
+/// Convert a serialized PSBT of any supported version to version 0, returning its original version
+pub(crate) fn to_v0(bytes: Vec<u8>) -> Result<(Vec<u8>, u32), BdkJniError> {
+    let raw = RawPsbt::parse(&bytes)?;
+    match raw.version()? {
+        0 => Ok((bytes, 0)),
+        2 => Ok((raw.into_v0()?.serialize(), 2)),
+        v => Err(BdkJniError::Unsupported(format!("PSBT version {}", v))),
+    }
+}
+
+/// Convert a serialized version 0 PSBT to `version`
+pub(crate) fn from_v0(bytes: Vec<u8>, version: u32) -> Result<Vec<u8>, BdkJniError> {
+    match version {
+        0 => Ok(bytes),
+        2 => Ok(RawPsbt::parse(&bytes)?.into_v2()?.serialize()),
+        v => Err(BdkJniError::Unsupported(format!("PSBT version {}", v))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bdk::bitcoin::hashes::hex::FromHex;
+    use bdk::bitcoin::util::psbt::PartiallySignedTransaction;
+
+    type Fields = Vec<(u8, Vec<u8>)>;
+
+    fn raw(global: Fields, inputs: Vec<Fields>, outputs: Vec<Fields>) -> Vec<u8> {
+        RawPsbt {
+            global: with(Map::new(), global),
+            inputs: inputs.into_iter().map(|i| with(Map::new(), i)).collect(),
+            outputs: outputs.into_iter().map(|o| with(Map::new(), o)).collect(),
+        }
+        .serialize()
+    }
+
+    fn v2_global(inputs: usize, outputs: usize) -> Fields {
+        vec![
+            (PSBT_GLOBAL_VERSION, serialize(&2u32)),
+            (PSBT_GLOBAL_TX_VERSION, serialize(&2u32)),
+            (PSBT_GLOBAL_INPUT_COUNT, serialize(&VarInt(inputs as u64))),
+            (PSBT_GLOBAL_OUTPUT_COUNT, serialize(&VarInt(outputs as u64))),
+        ]
+    }
+
+    fn v2_input(vout: u32) -> Fields {
+        vec![
+            (PSBT_IN_PREVIOUS_TXID, vec![0x11; 32]),
+            (PSBT_IN_OUTPUT_INDEX, serialize(&vout)),
+        ]
+    }
+
+    fn v2_output() -> Fields {
+        vec![
+            (PSBT_OUT_AMOUNT, serialize(&10_000i64)),
+            (
+                PSBT_OUT_SCRIPT,
+                Vec::from_hex("0014d85c2b71d0060b09c9886aeb815e50991dda124d").unwrap(),
+            ),
+        ]
+    }
+
+    fn v2(global: Fields, inputs: Vec<Fields>) -> Vec<u8> {
+        let mut fields = v2_global(inputs.len(), 1);
+        fields.extend(global);
+        raw(fields, inputs, vec![v2_output()])
+    }
+
+    fn unsigned_tx(v0: &[u8]) -> Transaction {
+        let raw = RawPsbt::parse(v0).unwrap();
+        deserialize(get(&raw.global, PSBT_GLOBAL_UNSIGNED_TX).unwrap()).unwrap()
+    }
+
+    fn locktime(global: Fields, inputs: Vec<Fields>) -> Result<u32, BdkJniError> {
+        let (v0, version) = to_v0(v2(global, inputs))?;
+        assert_eq!(version, 2);
+        Ok(unsigned_tx(&v0).lock_time)
+    }
+
+    fn with_field(mut fields: Fields, key_type: u8, value: Vec<u8>) -> Fields {
+        fields.push((key_type, value));
+        fields
+    }
+
+    fn without_field(fields: Fields, key_type: u8) -> Fields {
+        fields.into_iter().filter(|(k, _)| *k != key_type).collect()
+    }
+
+    fn v0() -> Vec<u8> {
+        let tx = Transaction {
+            version: 2,
+            lock_time: 1_234,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_hex(&"11".repeat(32)).unwrap(), 1),
+                script_sig: Script::new(),
+                sequence: 0xffff_fffd,
+                witness: vec![],
+            }],
+            output: vec![TxOut {
+                value: 10_000,
+                script_pubkey: Script::from(
+                    Vec::from_hex("0014d85c2b71d0060b09c9886aeb815e50991dda124d").unwrap(),
+                ),
+            }],
+        };
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx.clone()).unwrap();
+        psbt.inputs[0].witness_utxo = Some(tx.output[0].clone());
+
+        serialize(&psbt)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let v0 = v0();
+        let v2 = from_v0(v0.clone(), 2).unwrap();
+        assert_eq!(from_v0(v0.clone(), 0).unwrap(), v0);
+
+        let raw = RawPsbt::parse(&v2).unwrap();
+        assert_eq!(raw.version().unwrap(), 2);
+        assert!(get(&raw.global, PSBT_GLOBAL_UNSIGNED_TX).is_none());
+        assert_eq!(
+            get_u32(&raw.global, PSBT_GLOBAL_TX_VERSION).unwrap(),
+            Some(2)
+        );
+        assert_eq!(
+            get_u32(&raw.global, PSBT_GLOBAL_FALLBACK_LOCKTIME).unwrap(),
+            Some(1_234)
+        );
+        assert_eq!(get_count(&raw.global, PSBT_GLOBAL_INPUT_COUNT).unwrap(), 1);
+        assert_eq!(get_count(&raw.global, PSBT_GLOBAL_OUTPUT_COUNT).unwrap(), 1);
+        assert_eq!(
+            get_u32(&raw.inputs[0], PSBT_IN_OUTPUT_INDEX).unwrap(),
+            Some(1)
+        );
+        assert_eq!(
+            get_u32(&raw.inputs[0], PSBT_IN_SEQUENCE).unwrap(),
+            Some(0xffff_fffd)
+        );
+        assert_eq!(
+            get(&raw.outputs[0], PSBT_OUT_AMOUNT),
+            Some(&serialize(&10_000i64)[..])
+        );
+
+        let (back, version) = to_v0(v2).unwrap();
+        assert_eq!(version, 2);
+        assert_eq!(
+            deserialize::<PartiallySignedTransaction>(&back).unwrap(),
+            deserialize::<PartiallySignedTransaction>(&v0).unwrap()
+        );
+        assert_eq!(to_v0(v0.clone()).unwrap(), (v0, 0));
+    }
+
+    #[test]
+    fn test_valid_v2() {
+        let (v0, _) = to_v0(v2(vec![], vec![v2_input(0), v2_input(1)])).unwrap();
+        let tx = unsigned_tx(&v0);
+        assert_eq!(tx.version, 2);
+        assert_eq!(tx.input.len(), 2);
+        assert_eq!(tx.input[1].previous_output.vout, 1);
+        assert_eq!(tx.input[1].sequence, 0xffff_ffff);
+        assert_eq!(tx.output[0].value, 10_000);
+
+        // Modifiable flags and unknown fields are allowed
+        let modifiable = vec![(PSBT_GLOBAL_TX_MODIFIABLE, vec![0x03])];
+        assert!(to_v0(v2(modifiable, vec![v2_input(0)])).is_ok());
+        assert!(to_v0(v2(vec![(0xaa, vec![0x01])], vec![v2_input(0)])).is_ok());
+    }
+
+    #[test]
+    fn test_locktime() {
+        let time = |input: Fields, t: u32| {
+            with_field(input, PSBT_IN_REQUIRED_TIME_LOCKTIME, serialize(&t))
+        };
+        let height = |input: Fields, h: u32| {
+            with_field(input, PSBT_IN_REQUIRED_HEIGHT_LOCKTIME, serialize(&h))
+        };
+        let fallback = || vec![(PSBT_GLOBAL_FALLBACK_LOCKTIME, serialize(&1_000u32))];
+
+        // No locktime required, use the fallback or 0
+        assert_eq!(locktime(vec![], vec![v2_input(0)]).unwrap(), 0);
+        assert_eq!(locktime(fallback(), vec![v2_input(0)]).unwrap(), 1_000);
+
+        // The greatest required locktime, ignoring the fallback
+        let inputs = vec![
+            height(v2_input(0), 10_000),
+            height(v2_input(1), 20_000),
+            v2_input(2),
+        ];
+        assert_eq!(locktime(fallback(), inputs).unwrap(), 20_000);
+        let inputs = vec![
+            time(v2_input(0), 500_000_100),
+            time(v2_input(1), 500_000_000),
+        ];
+        assert_eq!(locktime(fallback(), inputs).unwrap(), 500_000_100);
+
+        // Inputs accepting both types pick the type required by the others
+        let both = height(time(v2_input(0), 500_000_100), 10_000);
+        let inputs = vec![both.clone(), time(v2_input(1), 500_000_200)];
+        assert_eq!(locktime(vec![], inputs).unwrap(), 500_000_200);
+        let inputs = vec![both.clone(), height(v2_input(1), 20_000)];
+        assert_eq!(locktime(vec![], inputs).unwrap(), 20_000);
+
+        // Heights are preferred when both types are possible
+        assert_eq!(locktime(vec![], vec![both, v2_input(1)]).unwrap(), 10_000);
+
+        // No type is accepted by every input
+        let inputs = vec![time(v2_input(0), 500_000_100), height(v2_input(1), 10_000)];
+        assert!(locktime(vec![], inputs).is_err());
+    }
+
+    #[test]
+    fn test_invalid_v2() {
+        let invalid = |global: Fields, inputs: Vec<Fields>, outputs: Vec<Fields>| {
+            to_v0(raw(global, inputs, outputs)).is_err()
+        };
+        let input = || vec![v2_input(0)];
+        let output = || vec![v2_output()];
+
+        // Missing global fields
+        for key_type in &[
+            PSBT_GLOBAL_TX_VERSION,
+            PSBT_GLOBAL_INPUT_COUNT,
+            PSBT_GLOBAL_OUTPUT_COUNT,
+        ] {
+            assert!(invalid(
+                without_field(v2_global(1, 1), *key_type),
+                input(),
+                output()
+            ));
+        }
+        // Unsigned tx in a version 2 PSBT
+        let unsigned = (PSBT_GLOBAL_UNSIGNED_TX, serialize(&unsigned_tx(&v0())));
+        assert!(invalid(
+            with_field(v2_global(1, 1), unsigned.0, unsigned.1),
+            input(),
+            output()
+        ));
+        // Missing input and output fields
+        for key_type in &[PSBT_IN_PREVIOUS_TXID, PSBT_IN_OUTPUT_INDEX] {
+            assert!(invalid(
+                v2_global(1, 1),
+                vec![without_field(v2_input(0), *key_type)],
+                output()
+            ));
+        }
+        for key_type in &[PSBT_OUT_AMOUNT, PSBT_OUT_SCRIPT] {
+            assert!(invalid(
+                v2_global(1, 1),
+                input(),
+                vec![without_field(v2_output(), *key_type)]
+            ));
+        }
+        // Locktimes of the wrong type
+        let time = (PSBT_IN_REQUIRED_TIME_LOCKTIME, serialize(&499_999_999u32));
+        assert!(invalid(
+            v2_global(1, 1),
+            vec![with_field(v2_input(0), time.0, time.1)],
+            output()
+        ));
+        let height = (PSBT_IN_REQUIRED_HEIGHT_LOCKTIME, serialize(&500_000_000u32));
+        assert!(invalid(
+            v2_global(1, 1),
+            vec![with_field(v2_input(0), height.0, height.1)],
+            output()
+        ));
+        // Negative amount
+        let negative = with_field(
+            without_field(v2_output(), PSBT_OUT_AMOUNT),
+            PSBT_OUT_AMOUNT,
+            serialize(&-1i64),
+        );
+        match to_v0(raw(v2_global(1, 1), input(), vec![negative])) {
+            Err(BdkJniError::Parsing(msg)) => assert!(msg.contains("malformed amount")),
+            _ => panic!("negative amounts must be rejected"),
+        }
+        // Unsupported version
+        let v3 = with_field(
+            without_field(v2_global(1, 1), PSBT_GLOBAL_VERSION),
+            PSBT_GLOBAL_VERSION,
+            serialize(&3u32),
+        );
+        assert!(invalid(v3, input(), output()));
+    }
+
+    #[test]
+    fn test_invalid_v0() {
+        let base = RawPsbt::parse(&v0()).unwrap();
+        let global = |fields: Fields| {
+            let mut global = base.global.clone();
+            global.extend(fields.into_iter().map(|(k, v)| (vec![k], v)));
+            RawPsbt {
+                global,
+                inputs: base.inputs.clone(),
+                outputs: base.outputs.clone(),
+            }
+            .serialize()
+        };
+
+        // Version 2 fields in a version 0 PSBT
+        assert!(to_v0(global(vec![(PSBT_GLOBAL_VERSION, serialize(&2u32))])).is_err());
+        for key_type in V2_GLOBAL_FIELDS {
+            assert!(to_v0(global(vec![(*key_type, serialize(&1u32))])).is_err());
+        }
+        for key_type in V2_INPUT_FIELDS {
+            let mut psbt = RawPsbt::parse(&base.serialize()).unwrap();
+            psbt.inputs[0] = with(psbt.inputs[0].clone(), vec![(*key_type, serialize(&1u32))]);
+            assert!(to_v0(psbt.serialize()).is_err());
+        }
+        for key_type in V2_OUTPUT_FIELDS {
+            let mut psbt = RawPsbt::parse(&base.serialize()).unwrap();
+            psbt.outputs[0] = with(psbt.outputs[0].clone(), vec![(*key_type, serialize(&1u32))]);
+            assert!(to_v0(psbt.serialize()).is_err());
+        }
+        // Missing unsigned tx
+        assert!(to_v0(raw(vec![], vec![], vec![])).is_err());
+    }
+}
@@ -0,0 +1,332 @@
+//! Proofs of reserves following BIP127: a transaction spending the wallet's UTXOs that can never
+//! be mined, since its first input spends a non-existent output committing to a challenge
+
+use std::collections::HashSet;
+
+use bdk::bitcoin::blockdata::opcodes;
+use bdk::bitcoin::blockdata::script::{Builder, Instruction};
+use bdk::bitcoin::consensus::encode::serialize;
+use bdk::bitcoin::hashes::{sha256d, Hash};
+use bdk::bitcoin::util::psbt::{Input, PartiallySignedTransaction};
+use bdk::bitcoin::{OutPoint, PubkeyHash, Script, SigHashType, Transaction, TxIn, TxOut, Txid};
+use bdk::blockchain::{noop_progress, Blockchain};
+use bdk::database::{BatchDatabase, Database};
+use bdk::wallet::tx_builder::TxOrdering;
+use bdk::Wallet;
+
+use crate::{verify_script, BdkJniError};
+
+const CHALLENGE_PREFIX: &str = "Proof-of-Reserves: ";
+
+/// The outpoint spent by the first input of a proof for `message`
+fn challenge_outpoint(message: &str) -> OutPoint {
+    let hash = sha256d::Hash::hash(format!("{}{}", CHALLENGE_PREFIX, message).as_bytes());
+    OutPoint::new(Txid::from_hash(hash), 0)
+}
+
+/// P2PKH of a key hash with no known preimage, receiving the whole value of the proof
+fn unspendable_script() -> Script {
+    Script::new_p2pkh(&PubkeyHash::hash(&[0]))
+}
+
+/// The sighash byte of every signature in the final scriptSig and witness of `txin`
+///
+/// Signatures are the pushes that look like DER encoded ones. The sighash types of the PSBT
+/// can't be used, since they are chosen by the prover and not committed to by the signatures.
+fn signature_sighashes(txin: &TxIn) -> Vec<u8> {
+    let script_sig_pushes =
+        txin.script_sig
+            .instructions()
+            .filter_map(|instruction| match instruction {
+                Ok(Instruction::PushBytes(data)) => Some(data),
+                _ => None,
+            });
+
+    script_sig_pushes
+        .chain(txin.witness.iter().map(Vec::as_slice))
+        .filter(|data| data.len() >= 9 && data[0] == 0x30 && data[1] as usize + 3 == data.len())
+        .map(|data| data[data.len() - 1])
+        .collect()
+}
+
+/// Build an unsigned proof of reserves for `message`, over all the wallet's UTXOs or only `utxos`
+///
+/// The challenge input is already finalized, so the proof can be signed like any other PSBT.
+pub(crate) fn create<B, D>(
+    wallet: &Wallet<B, D>,
+    message: &str,
+    utxos: Option<Vec<OutPoint>>,
+) -> Result<PartiallySignedTransaction, BdkJniError>
+where
+    B: Blockchain,
+    D: BatchDatabase,
+{
+    if message.is_empty() {
+        return Err(BdkJniError::Parsing(
+            "The challenge message can't be empty".to_string(),
+        ));
+    }
+
+    let challenge = Input {
+        witness_utxo: Some(TxOut {
+            value: 0,
+            script_pubkey: Builder::new().push_opcode(opcodes::OP_TRUE).into_script(),
+        }),
+        final_script_sig: Some(Script::new()),
+        ..Default::default()
+    };
+
+    let mut builder = wallet.build_tx();
+    builder
+        .add_foreign_utxo(challenge_outpoint(message), challenge, 0)?
+        .only_witness_utxo()
+        .ordering(TxOrdering::Untouched)
+        .fee_absolute(0)
+        .drain_to(unspendable_script());
+    match utxos {
+        Some(utxos) => {
+            builder.add_utxos(&utxos)?.manually_selected_only();
+        }
+        None => {
+            builder.drain_wallet();
+        }
+    }
+    let (mut psbt, _) = builder.finish()?;
+
+    // `only_witness_utxo` is required by the challenge input, which has no previous transaction,
+    // add back the ones of the wallet's inputs so that signing doesn't have to trust witness UTXOs
+    let database = wallet.database();
+    for (input, txin) in psbt
+        .inputs
+        .iter_mut()
+        .zip(psbt.global.unsigned_tx.input.iter())
+        .skip(1)
+    {
+        input.non_witness_utxo = database.get_raw_tx(&txin.previous_output.txid)?;
+    }
+
+    Ok(psbt)
+}
+
+/// Check everything about a proof for `message` that doesn't depend on the UTXOs it spends, and
+/// return its finalized transaction
+fn check_structure(
+    psbt: &PartiallySignedTransaction,
+    message: &str,
+) -> Result<Transaction, BdkJniError> {
+    let invalid = |reason: &str| BdkJniError::InvalidReserveProof(reason.to_string());
+
+    let not_finalized = psbt
+        .inputs
+        .iter()
+        .enumerate()
+        .filter(|(_, i)| i.final_script_sig.is_none() && i.final_script_witness.is_none())
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    if !not_finalized.is_empty() {
+        return Err(BdkJniError::PsbtNotFinalized(not_finalized));
+    }
+
+    let tx = psbt.clone().extract_tx();
+    if tx.input.len() < 2 {
+        return Err(invalid(
+            "The proof must spend the challenge and at least one UTXO",
+        ));
+    }
+    for (index, txin) in tx.input.iter().enumerate().skip(1) {
+        let sighashes = signature_sighashes(txin);
+        if sighashes.is_empty() || sighashes.iter().any(|s| *s != SigHashType::All as u8) {
+            return Err(BdkJniError::InvalidReserveProof(format!(
+                "Input {} must be signed with SIGHASH_ALL only",
+                index
+            )));
+        }
+    }
+    if tx.input[0].previous_output != challenge_outpoint(message) {
+        return Err(invalid(
+            "The first input doesn't match the challenge message",
+        ));
+    }
+    if tx
+        .input
+        .iter()
+        .map(|i| i.previous_output)
+        .collect::<HashSet<_>>()
+        .len()
+        != tx.input.len()
+    {
+        return Err(invalid("The proof spends the same UTXO more than once"));
+    }
+    if tx.output.len() != 1 || tx.output[0].script_pubkey != unspendable_script() {
+        return Err(invalid("The proof must have a single unspendable output"));
+    }
+
+    Ok(tx)
+}
+
+/// Verify a finalized proof of reserves for `message` against the wallet's UTXOs and return the
+/// proven amount
+///
+/// The wallet is synced first, so that UTXOs spent since its last sync don't count.
+pub(crate) fn verify<B, D>(
+    wallet: &Wallet<B, D>,
+    psbt: &PartiallySignedTransaction,
+    message: &str,
+) -> Result<u64, BdkJniError>
+where
+    B: Blockchain,
+    D: BatchDatabase,
+{
+    let tx = check_structure(psbt, message)?;
+
+    wallet.sync(noop_progress(), None)?;
+    let unspent = wallet.list_unspent()?;
+    let serialized = serialize(&tx);
+    let mut amount = 0;
+    for (index, txin) in tx.input.iter().enumerate().skip(1) {
+        let utxo = unspent
+            .iter()
+            .find(|utxo| utxo.outpoint == txin.previous_output)
+            .ok_or_else(|| {
+                BdkJniError::InvalidReserveProof(format!(
+                    "Input {} is spent or not part of the wallet",
+                    index
+                ))
+            })?;
+        if !verify_script(
+            &utxo.txout.script_pubkey,
+            utxo.txout.value,
+            &serialized,
+            index,
+        )? {
+            return Err(BdkJniError::InvalidReserveProof(format!(
+                "Input {} has an invalid signature",
+                index
+            )));
+        }
+
+        amount += utxo.txout.value;
+    }
+
+    if tx.output[0].value != amount {
+        return Err(BdkJniError::InvalidReserveProof(
+            "The output value doesn't match the proven amount".to_string(),
+        ));
+    }
+
+    Ok(amount)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MESSAGE: &str = "Challenge from the auditor";
+
+    /// A DER-looking signature followed by `sighash`
+    fn signature(sighash: u8) -> Vec<u8> {
+        let mut signature = vec![0x30, 6, 0x02, 1, 1, 0x02, 1, 1];
+        signature.push(sighash);
+        signature
+    }
+
+    /// A finalized proof spending `utxos`, each one with a single signature using `sighash`
+    fn proof(message: &str, utxos: &[OutPoint], sighash: u8) -> PartiallySignedTransaction {
+        let tx = Transaction {
+            version: 1,
+            lock_time: 0,
+            input: std::iter::once(challenge_outpoint(message))
+                .chain(utxos.iter().cloned())
+                .map(|previous_output| TxIn {
+                    previous_output,
+                    ..Default::default()
+                })
+                .collect(),
+            output: vec![TxOut {
+                value: 0,
+                script_pubkey: unspendable_script(),
+            }],
+        };
+
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].final_script_sig = Some(Script::new());
+        for input in psbt.inputs.iter_mut().skip(1) {
+            input.final_script_witness = Some(vec![signature(sighash), vec![0x02; 33]]);
+        }
+
+        psbt
+    }
+
+    fn utxo(vout: u32) -> OutPoint {
+        OutPoint::new(Txid::from_hash(sha256d::Hash::hash(b"utxo")), vout)
+    }
+
+    #[test]
+    fn test_signature_sighashes() {
+        let mut txin = TxIn::default();
+        assert!(signature_sighashes(&txin).is_empty());
+
+        txin.script_sig = Builder::new()
+            .push_slice(&signature(0x81))
+            .push_slice(&[0x02; 33])
+            .into_script();
+        assert_eq!(signature_sighashes(&txin), vec![0x81]);
+
+        txin.script_sig = Builder::new()
+            .push_int(0)
+            .push_slice(&signature(0x01))
+            .push_slice(&signature(0x83))
+            .into_script();
+        txin.witness = vec![signature(0x02), vec![0x02; 33]];
+        assert_eq!(signature_sighashes(&txin), vec![0x01, 0x83, 0x02]);
+    }
+
+    #[test]
+    fn test_check_structure() {
+        let tx = check_structure(&proof(MESSAGE, &[utxo(0), utxo(1)], 0x01), MESSAGE).unwrap();
+        assert_eq!(tx.input.len(), 3);
+    }
+
+    #[test]
+    fn test_check_structure_invalid() {
+        let is_invalid = |psbt: &PartiallySignedTransaction, message: &str| {
+            matches!(
+                check_structure(psbt, message),
+                Err(BdkJniError::InvalidReserveProof(_))
+            )
+        };
+
+        // Signatures that don't commit to the challenge input
+        assert!(is_invalid(&proof(MESSAGE, &[utxo(0)], 0x81), MESSAGE));
+        assert!(is_invalid(&proof(MESSAGE, &[utxo(0)], 0x02), MESSAGE));
+        // An input without any signature
+        let mut psbt = proof(MESSAGE, &[utxo(0)], 0x01);
+        psbt.inputs[1].final_script_witness = Some(vec![vec![0x51]]);
+        assert!(is_invalid(&psbt, MESSAGE));
+
+        assert!(is_invalid(
+            &proof(MESSAGE, &[utxo(0)], 0x01),
+            "Another challenge"
+        ));
+        assert!(is_invalid(&proof(MESSAGE, &[], 0x01), MESSAGE));
+        assert!(is_invalid(
+            &proof(MESSAGE, &[utxo(0), utxo(0)], 0x01),
+            MESSAGE
+        ));
+
+        let mut psbt = proof(MESSAGE, &[utxo(0)], 0x01);
+        psbt.global.unsigned_tx.output[0].script_pubkey = Script::new();
+        assert!(is_invalid(&psbt, MESSAGE));
+    }
+
+    #[test]
+    fn test_check_structure_not_finalized() {
+        let mut psbt = proof(MESSAGE, &[utxo(0), utxo(1)], 0x01);
+        psbt.inputs[2].final_script_witness = None;
+
+        assert!(matches!(
+            check_structure(&psbt, MESSAGE),
+            Err(BdkJniError::PsbtNotFinalized(inputs)) if inputs == vec![2]
+        ));
+    }
+}
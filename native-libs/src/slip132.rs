@@ -0,0 +1,237 @@
+//! SLIP-132 extended keys (ypub, zpub, Ypub, Zpub and their testnet and private variants)
+//!
+//! bitcoin only understands xpub/xprv and tpub/tprv, SLIP-132 keys are the same keys with
+//! different version bytes that hint at the script type. They are converted back and forth by
+//! swapping the version bytes.
+
+use serde::{Deserialize, Serialize};
+
+use bdk::bitcoin::hashes::hex::ToHex;
+use bdk::bitcoin::util::base58;
+use bdk::bitcoin::Network;
+use bdk::descriptor::get_checksum;
+
+use crate::multisig::MultisigScriptType;
+use crate::templates::Template;
+use crate::{descriptor_with_checksum, BdkJniError};
+
+/// Length of a base58 encoded extended key
+const ENCODED_KEY_LEN: usize = 111;
+
+/// Family of version bytes, named after their mainnet public key prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum KeyVersion {
+    /// P2PKH or P2SH, also used by tpub/tprv
+    #[serde(rename = "xpub")]
+    Xpub,
+    /// P2WPKH nested in P2SH, upub/uprv on testnet
+    #[serde(rename = "ypub")]
+    Ypub,
+    /// P2WPKH, vpub/vprv on testnet
+    #[serde(rename = "zpub")]
+    Zpub,
+    /// Multisig P2WSH nested in P2SH, Upub/Uprv on testnet
+    #[serde(rename = "Ypub")]
+    YpubMultisig,
+    /// Multisig P2WSH, Vpub/Vprv on testnet
+    #[serde(rename = "Zpub")]
+    ZpubMultisig,
+}
+
+impl KeyVersion {
+    /// Version bytes, indexed by `[mainnet][private]`
+    fn bytes(self) -> [[[u8; 4]; 2]; 2] {
+        match self {
+            KeyVersion::Xpub => [
+                [[0x04, 0x35, 0x87, 0xcf], [0x04, 0x35, 0x83, 0x94]],
+                [[0x04, 0x88, 0xb2, 0x1e], [0x04, 0x88, 0xad, 0xe4]],
+            ],
+            KeyVersion::Ypub => [
+                [[0x04, 0x4a, 0x52, 0x62], [0x04, 0x4a, 0x4e, 0x28]],
+                [[0x04, 0x9d, 0x7c, 0xb2], [0x04, 0x9d, 0x78, 0x78]],
+            ],
+            KeyVersion::Zpub => [
+                [[0x04, 0x5f, 0x1c, 0xf6], [0x04, 0x5f, 0x18, 0xbc]],
+                [[0x04, 0xb2, 0x47, 0x46], [0x04, 0xb2, 0x43, 0x0c]],
+            ],
+            KeyVersion::YpubMultisig => [
+                [[0x02, 0x42, 0x89, 0xef], [0x02, 0x42, 0x85, 0xb5]],
+                [[0x02, 0x95, 0xb4, 0x3f], [0x02, 0x95, 0xb0, 0x05]],
+            ],
+            KeyVersion::ZpubMultisig => [
+                [[0x02, 0x57, 0x54, 0x83], [0x02, 0x57, 0x50, 0x48]],
+                [[0x02, 0xaa, 0x7e, 0xd3], [0x02, 0xaa, 0x7a, 0x99]],
+            ],
+        }
+    }
+
+    const ALL: [KeyVersion; 5] = [
+        KeyVersion::Xpub,
+        KeyVersion::Ypub,
+        KeyVersion::Zpub,
+        KeyVersion::YpubMultisig,
+        KeyVersion::ZpubMultisig,
+    ];
+
+    /// The single-key template matching this version, if any
+    pub(crate) fn template(self) -> Option<Template> {
+        match self {
+            KeyVersion::Ypub => Some(Template::Bip49),
+            KeyVersion::Zpub => Some(Template::Bip84),
+            _ => None,
+        }
+    }
+
+    /// The multisig script type matching this version, if any
+    pub(crate) fn multisig_script_type(self) -> Option<MultisigScriptType> {
+        match self {
+            KeyVersion::YpubMultisig => Some(MultisigScriptType::P2shP2wsh),
+            KeyVersion::ZpubMultisig => Some(MultisigScriptType::P2wsh),
+            _ => None,
+        }
+    }
+}
+
+/// An extended key split in its version and payload
+#[derive(Debug, Clone)]
+pub(crate) struct DecodedKey {
+    pub(crate) version: KeyVersion,
+    pub(crate) network: Network,
+    pub(crate) private: bool,
+    /// Serialized key without the version bytes
+    payload: Vec<u8>,
+}
+
+impl DecodedKey {
+    /// Encode the key with the version bytes of `version`
+    pub(crate) fn encode(&self, version: KeyVersion) -> String {
+        let mainnet = self.network == Network::Bitcoin;
+        let mut data = version.bytes()[mainnet as usize][self.private as usize].to_vec();
+        data.extend_from_slice(&self.payload);
+
+        base58::check_encode_slice(&data)
+    }
+}
+
+/// Decode an extended key with any of the known version bytes
+pub(crate) fn decode(key: &str) -> Result<DecodedKey, BdkJniError> {
+    let data = base58::from_check(key.trim()).map_err(|e| BdkJniError::Parsing(e.to_string()))?;
+    if data.len() != 78 {
+        return Err(BdkJniError::Parsing(format!(
+            "Invalid extended key length {}",
+            data.len()
+        )));
+    }
+
+    for version in KeyVersion::ALL.iter() {
+        for (mainnet, bytes) in version.bytes().iter().enumerate() {
+            for (private, bytes) in bytes.iter().enumerate() {
+                if data[..4] == bytes[..] {
+                    return Ok(DecodedKey {
+                        version: *version,
+                        network: match mainnet {
+                            1 => Network::Bitcoin,
+                            _ => Network::Testnet,
+                        },
+                        private: private == 1,
+                        payload: data[4..].to_vec(),
+                    });
+                }
+            }
+        }
+    }
+
+    Err(BdkJniError::Parsing(format!(
+        "Unknown extended key version {}",
+        data[..4].to_hex()
+    )))
+}
+
+/// Convert a SLIP-132 key to the xpub/xprv or tpub/tprv understood by bitcoin, other keys are
+/// returned as-is
+pub(crate) fn normalize_key(key: &str) -> String {
+    match decode(key) {
+        Ok(decoded) if decoded.version != KeyVersion::Xpub => decoded.encode(KeyVersion::Xpub),
+        _ => key.to_string(),
+    }
+}
+
+/// Replace all the SLIP-132 keys of a descriptor by their xpub/xprv or tpub/tprv equivalent
+///
+/// A checksum is verified against the original descriptor, then recomputed for the normalized one
+/// if a key has been replaced.
+pub(crate) fn normalize_descriptor(descriptor: &str) -> Result<String, BdkJniError> {
+    let mut parts = descriptor.splitn(2, '#');
+    let body = parts.next().unwrap_or_default();
+    let checksum = parts.next();
+    if let Some(checksum) = checksum {
+        let expected = get_checksum(body).map_err(bdk::Error::from)?;
+        if checksum != expected {
+            return Err(BdkJniError::Parsing(format!(
+                "Invalid descriptor checksum {}, expected {}",
+                checksum, expected
+            )));
+        }
+    }
+
+    let mut normalized = String::with_capacity(descriptor.len());
+    let mut replaced = false;
+
+    let mut rest = body;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_alphanumeric()) {
+        normalized.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = rest
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or_else(|| rest.len());
+        let token = &rest[..end];
+        let key = match token.len() {
+            ENCODED_KEY_LEN => normalize_key(token),
+            _ => token.to_string(),
+        };
+        replaced |= key != token;
+        normalized.push_str(&key);
+        rest = &rest[end..];
+    }
+    normalized.push_str(rest);
+
+    match (replaced, checksum) {
+        (false, _) => Ok(descriptor.to_string()),
+        (true, Some(_)) => Ok(descriptor_with_checksum(&normalized)?.0),
+        (true, None) => Ok(normalized),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ZPUB: &str = "zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs";
+    const XPUB: &str = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V";
+
+    #[test]
+    fn test_normalize_descriptor() {
+        let zpub = format!("wpkh([73c5da0a/84'/0'/0']{}/0/*)", ZPUB);
+        let xpub = format!("wpkh([73c5da0a/84'/0'/0']{}/0/*)", XPUB);
+
+        assert_eq!(normalize_descriptor(&zpub).unwrap(), xpub);
+        assert_eq!(
+            normalize_descriptor(&format!("{}#5t3nun4e", zpub)).unwrap(),
+            format!("{}#wc3n3van", xpub)
+        );
+        assert_eq!(
+            normalize_descriptor(&format!("{}#wc3n3van", xpub)).unwrap(),
+            format!("{}#wc3n3van", xpub)
+        );
+    }
+
+    #[test]
+    fn test_normalize_descriptor_invalid_checksum() {
+        let zpub = format!("wpkh([73c5da0a/84'/0'/0']{}/0/*)", ZPUB);
+
+        // The checksum of the normalized descriptor doesn't make the original one valid
+        assert!(normalize_descriptor(&format!("{}#wc3n3van", zpub)).is_err());
+        assert!(normalize_descriptor(&format!("{}#5t3nun4f", zpub)).is_err());
+    }
+}
@@ -0,0 +1,70 @@
+//! SLIP-39 Shamir backups of a master secret, on top of `sssmc39`
+
+use serde::{Deserialize, Serialize};
+
+use crate::BdkJniError;
+
+/// A group of shares, `threshold` of its `count` members are needed to recover the group
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub(crate) struct Slip39Group {
+    pub(crate) threshold: u8,
+    pub(crate) count: u8,
+}
+
+fn slip39_error(e: sssmc39::Error) -> BdkJniError {
+    BdkJniError::Mnemonic(e.to_string())
+}
+
+/// Split `master_secret` into groups of share mnemonics, `group_threshold` groups are needed to
+/// recover it
+pub(crate) fn split(
+    master_secret: &[u8],
+    group_threshold: u8,
+    groups: &[Slip39Group],
+    passphrase: &str,
+    iteration_exponent: u8,
+) -> Result<Vec<Vec<String>>, BdkJniError> {
+    let groups = groups
+        .iter()
+        .map(|g| (g.threshold, g.count))
+        .collect::<Vec<_>>();
+    let shares = sssmc39::generate_mnemonics(
+        group_threshold,
+        &groups,
+        master_secret,
+        passphrase,
+        iteration_exponent,
+    )
+    .map_err(slip39_error)?;
+
+    shares
+        .iter()
+        .map(|group| {
+            Ok(group
+                .mnemonic_list()
+                .map_err(slip39_error)?
+                .into_iter()
+                .map(|words| words.join(" "))
+                .collect())
+        })
+        .collect()
+}
+
+/// Recover the master secret from enough share mnemonics
+///
+/// The checksum of each share is verified, as well as the identifier and parameters shared by
+/// all of them, so shares from different backups can't be mixed.
+pub(crate) fn combine(shares: &[String], passphrase: &str) -> Result<Vec<u8>, BdkJniError> {
+    let shares = shares
+        .iter()
+        .map(|share| {
+            share
+                .to_lowercase()
+                .split_whitespace()
+                .map(String::from)
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    sssmc39::combine_mnemonics(&shares, passphrase).map_err(slip39_error)
+}
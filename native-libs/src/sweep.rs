@@ -0,0 +1,329 @@
+//! Sweep the funds of a standalone private key, such as a paper wallet, into a wallet
+
+use bdk::bitcoin::blockdata::script::Builder;
+use bdk::bitcoin::consensus::encode::serialize;
+use bdk::bitcoin::secp256k1::{All, Secp256k1};
+use bdk::bitcoin::util::psbt::PartiallySignedTransaction;
+use bdk::bitcoin::{Address, OutPoint, PrivateKey, Script, Transaction, TxIn, TxOut};
+use bdk::blockchain::{noop_progress, Blockchain};
+use bdk::database::{BatchDatabase, BatchOperations, Database, MemoryDatabase};
+use bdk::miniscript::psbt as miniscript_psbt;
+use bdk::signer::Signer;
+use bdk::wallet::AddressIndex::LastUnused;
+use bdk::{FeeRate, KeychainKind, Wallet};
+
+use crate::BdkJniError;
+
+/// Dust relay fee of Bitcoin Core, in sat/vB, used to compute the dust threshold of an output
+const DUST_RELAY_FEE: u64 = 3;
+
+/// Size of the largest DER signature, followed by the sighash byte
+const MAX_SIGNATURE_SIZE: usize = 73;
+
+/// Signal RBF on the sweeping transaction, like bdk does by default
+const SEQUENCE_RBF: u32 = 0xFFFF_FFFD;
+
+#[derive(Debug)]
+pub(crate) struct Sweep {
+    pub(crate) transaction: Transaction,
+    pub(crate) amount: u64,
+    pub(crate) fee: u64,
+}
+
+/// The scripts `key` can receive on, with the redeem script of the nested ones
+fn key_scripts(key: &PrivateKey, secp: &Secp256k1<All>) -> Vec<(Script, Option<Script>)> {
+    let pubkey = key.public_key(secp);
+    let mut scripts = vec![(Address::p2pkh(&pubkey, key.network).script_pubkey(), None)];
+
+    // Segwit scripts are only standard with compressed keys
+    if let (Ok(p2wpkh), Ok(p2sh)) = (
+        Address::p2wpkh(&pubkey, key.network),
+        Address::p2shwpkh(&pubkey, key.network),
+    ) {
+        scripts.push((p2wpkh.script_pubkey(), None));
+        scripts.push((p2sh.script_pubkey(), Some(p2wpkh.script_pubkey())));
+    }
+
+    scripts
+}
+
+/// The smallest value of an output to `script` that Bitcoin Core relays, following its
+/// `GetDustThreshold`
+fn dust_threshold(script: &Script) -> u64 {
+    let output_size = serialize(&TxOut {
+        value: 0,
+        script_pubkey: script.clone(),
+    })
+    .len();
+    // Size of the input spending it, the witness data is discounted
+    let input_size = if script.is_witness_program() { 67 } else { 148 };
+
+    (output_size + input_size) as u64 * DUST_RELAY_FEE
+}
+
+/// Fill every input of `tx` with a maximum-size satisfaction, to estimate the final weight
+/// before signing
+fn dummy_satisfy(
+    tx: &Transaction,
+    utxos: &[(TxOut, Option<Script>)],
+    key: &PrivateKey,
+    secp: &Secp256k1<All>,
+) -> Transaction {
+    let pubkey = key.public_key(secp).to_bytes();
+    let signature = vec![0; MAX_SIGNATURE_SIZE];
+
+    let mut tx = tx.clone();
+    for (txin, (txout, redeem_script)) in tx.input.iter_mut().zip(utxos.iter()) {
+        if txout.script_pubkey.is_p2pkh() {
+            txin.script_sig = Builder::new()
+                .push_slice(&signature)
+                .push_slice(&pubkey)
+                .into_script();
+        } else {
+            txin.witness = vec![signature.clone(), pubkey.clone()];
+            if let Some(redeem_script) = redeem_script {
+                txin.script_sig = Builder::new()
+                    .push_slice(redeem_script.as_bytes())
+                    .into_script();
+            }
+        }
+    }
+
+    tx
+}
+
+/// Sign and finalize every input with `key`
+fn sign(
+    psbt: &PartiallySignedTransaction,
+    key: &PrivateKey,
+    secp: &Secp256k1<All>,
+) -> Result<Transaction, BdkJniError> {
+    let mut psbt = psbt.clone();
+    for index in 0..psbt.inputs.len() {
+        key.sign(&mut psbt, Some(index), secp)?;
+    }
+    miniscript_psbt::finalize(&mut psbt, secp)
+        .map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?;
+
+    Ok(psbt.extract_tx())
+}
+
+/// Build a transaction sending all the `utxos` of `key` to `destination`, signed with `key`
+///
+/// The fee is estimated with maximum-size signatures, so the final fee rate is never lower than
+/// `fee_rate`.
+fn sweep_utxos(
+    utxos: &[(OutPoint, TxOut, Option<Transaction>)],
+    key: &PrivateKey,
+    destination: Script,
+    fee_rate: FeeRate,
+    secp: &Secp256k1<All>,
+) -> Result<Sweep, BdkJniError> {
+    let scripts = key_scripts(key, secp);
+    let amount = utxos.iter().map(|(_, txout, _)| txout.value).sum::<u64>();
+    if utxos.is_empty() {
+        return Err(bdk::Error::Generic("No funds to sweep".to_string()).into());
+    }
+
+    let tx = Transaction {
+        version: 2,
+        lock_time: 0,
+        input: utxos
+            .iter()
+            .map(|(outpoint, _, _)| TxIn {
+                previous_output: *outpoint,
+                sequence: SEQUENCE_RBF,
+                ..Default::default()
+            })
+            .collect(),
+        output: vec![TxOut {
+            script_pubkey: destination,
+            value: amount,
+        }],
+    };
+
+    let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx)
+        .map_err(|e| BdkJniError::Parsing(format!("{:?}", e)))?;
+    let mut spent = Vec::with_capacity(utxos.len());
+    for (input, (_, txout, prev_tx)) in psbt.inputs.iter_mut().zip(utxos.iter()) {
+        let redeem_script = scripts
+            .iter()
+            .find(|(script, _)| *script == txout.script_pubkey)
+            .ok_or_else(|| {
+                BdkJniError::Parsing(format!(
+                    "Output script {} doesn't belong to the key",
+                    txout.script_pubkey
+                ))
+            })?
+            .1
+            .clone();
+
+        input.non_witness_utxo = prev_tx.clone();
+        if !txout.script_pubkey.is_p2pkh() {
+            input.witness_utxo = Some(txout.clone());
+        }
+        input.redeem_script = redeem_script.clone();
+        spent.push((txout.clone(), redeem_script));
+    }
+
+    let weight = dummy_satisfy(&psbt.global.unsigned_tx, &spent, key, secp).get_weight();
+    let vsize = (weight + 3) / 4;
+    let fee = (fee_rate.as_sat_vb() * vsize as f32).ceil() as u64;
+    let value = amount.saturating_sub(fee);
+    let dust = dust_threshold(&psbt.global.unsigned_tx.output[0].script_pubkey);
+    if value < dust {
+        return Err(bdk::Error::InsufficientFunds {
+            needed: fee + dust,
+            available: amount,
+        }
+        .into());
+    }
+    psbt.global.unsigned_tx.output[0].value = value;
+
+    Ok(Sweep {
+        transaction: sign(&psbt, key, secp)?,
+        amount,
+        fee,
+    })
+}
+
+/// Find the unspent outputs of `key` with the wallet's blockchain and build a signed
+/// transaction sending them all to the last unused address of the wallet
+///
+/// The outputs are looked up in a temporary in-memory database, so the key is never added to
+/// the wallet.
+pub(crate) fn sweep<B, D>(
+    wallet: &Wallet<B, D>,
+    key: &PrivateKey,
+    fee_rate: FeeRate,
+) -> Result<Sweep, BdkJniError>
+where
+    B: Blockchain,
+    D: BatchDatabase,
+{
+    let secp = Secp256k1::new();
+    let scripts = key_scripts(key, &secp);
+
+    let mut database = MemoryDatabase::new();
+    for (index, (script, _)) in scripts.iter().enumerate() {
+        database.set_script_pubkey(script, KeychainKind::External, index as u32)?;
+    }
+    wallet.client().setup(&mut database, noop_progress())?;
+
+    let utxos = database
+        .iter_utxos()?
+        .into_iter()
+        .map(|utxo| {
+            let prev_tx = database.get_raw_tx(&utxo.outpoint.txid)?;
+            Ok((utxo.outpoint, utxo.txout, prev_tx))
+        })
+        .collect::<Result<Vec<_>, BdkJniError>>()?;
+    // Failed or abandoned sweeps must not move the derivation index forward
+    let destination = wallet.get_address(LastUnused)?.script_pubkey();
+
+    sweep_utxos(&utxos, key, destination, fee_rate, &secp)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bdk::bitcoin::secp256k1::SecretKey;
+    use bdk::bitcoin::Network;
+
+    use crate::verify_script;
+
+    fn key(byte: u8) -> PrivateKey {
+        PrivateKey {
+            compressed: true,
+            network: Network::Bitcoin,
+            key: SecretKey::from_slice(&[byte; 32]).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_dust_threshold() {
+        let secp = Secp256k1::new();
+        let scripts = key_scripts(&key(1), &secp);
+
+        assert_eq!(dust_threshold(&scripts[0].0), 546);
+        assert_eq!(dust_threshold(&scripts[1].0), 294);
+        assert_eq!(dust_threshold(&scripts[2].0), 540);
+    }
+
+    #[test]
+    fn test_sweep_utxos() {
+        let secp = Secp256k1::new();
+        let key = key(1);
+        let destination = key_scripts(&self::key(2), &secp)[1].0.clone();
+
+        // A mocked transaction funding every script of the key
+        let prev_tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn::default()],
+            output: key_scripts(&key, &secp)
+                .into_iter()
+                .map(|(script_pubkey, _)| TxOut {
+                    value: 50_000,
+                    script_pubkey,
+                })
+                .collect(),
+        };
+        let utxos = prev_tx
+            .output
+            .iter()
+            .enumerate()
+            .map(|(vout, txout)| {
+                (
+                    OutPoint::new(prev_tx.txid(), vout as u32),
+                    txout.clone(),
+                    Some(prev_tx.clone()),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let fee_rate = FeeRate::from_sat_per_vb(2.0);
+        let sweep = sweep_utxos(&utxos, &key, destination.clone(), fee_rate, &secp).unwrap();
+        let tx = sweep.transaction;
+
+        assert_eq!(sweep.amount, 150_000);
+        assert_eq!(tx.output.len(), 1);
+        assert_eq!(tx.output[0].script_pubkey, destination);
+        assert_eq!(tx.output[0].value, sweep.amount - sweep.fee);
+        // The estimate never pays less than the requested fee rate
+        assert!(sweep.fee >= 2 * ((tx.get_weight() as u64 + 3) / 4));
+
+        let serialized = serialize(&tx);
+        for (index, (_, txout, _)) in utxos.iter().enumerate() {
+            assert!(verify_script(&txout.script_pubkey, txout.value, &serialized, index).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_sweep_dust() {
+        let secp = Secp256k1::new();
+        let key = key(1);
+        let (script_pubkey, _) = key_scripts(&key, &secp).remove(1);
+        let utxos = vec![(
+            OutPoint::default(),
+            TxOut {
+                value: 600,
+                script_pubkey,
+            },
+            None,
+        )];
+        let fee_rate = FeeRate::from_sat_per_vb(1.0);
+
+        // Above the dust threshold of a P2WPKH output, but not of a P2PKH one
+        let p2wpkh = key_scripts(&self::key(2), &secp).remove(1).0;
+        assert!(sweep_utxos(&utxos, &key, p2wpkh, fee_rate, &secp).is_ok());
+        let p2pkh = key_scripts(&self::key(2), &secp).remove(0).0;
+        assert!(matches!(
+            sweep_utxos(&utxos, &key, p2pkh, fee_rate, &secp),
+            Err(BdkJniError::WalletError(
+                bdk::Error::InsufficientFunds { .. }
+            ))
+        ));
+    }
+}
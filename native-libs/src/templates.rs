@@ -0,0 +1,232 @@
+//! Single-key descriptors following BIP44, BIP49 and BIP84, built with bdk's templates
+
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use bdk::bitcoin::secp256k1::Secp256k1;
+use bdk::bitcoin::util::bip32::{
+    ChildNumber, DerivationPath, ExtendedPrivKey, ExtendedPubKey, KeySource,
+};
+use bdk::bitcoin::{Address, Network};
+use bdk::descriptor::template::{DescriptorTemplate, P2Pkh, P2Wpkh, P2Wpkh_P2Sh};
+use bdk::keys::DerivableKey;
+use bdk::miniscript::{Legacy, Segwitv0};
+use bdk::KeychainKind;
+
+use crate::mnemonic::WordlistLanguage;
+use crate::slip132;
+use crate::{descriptor_with_checksum, master_xprv, parse_key_origin, BdkJniError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Template {
+    /// `pkh()` at `m/44'/coin'/account'`
+    Bip44,
+    /// `sh(wpkh())` at `m/49'/coin'/account'`
+    Bip49,
+    /// `wpkh()` at `m/84'/coin'/account'`
+    Bip84,
+}
+
+impl Template {
+    pub(crate) const ALL: [Template; 3] = [Template::Bip44, Template::Bip49, Template::Bip84];
+
+    fn purpose(self) -> u32 {
+        match self {
+            Template::Bip44 => 44,
+            Template::Bip49 => 49,
+            Template::Bip84 => 84,
+        }
+    }
+}
+
+/// The key of a template, either a master key as an xprv or mnemonic, or an account xpub
+#[derive(Debug, Deserialize)]
+pub(crate) struct TemplateParams {
+    /// Inferred from the version of SLIP-132 keys when missing, defaults to BIP84 for master keys
+    pub(crate) template: Option<Template>,
+
+    pub(crate) xprv: Option<String>,
+    pub(crate) mnemonic: Option<String>,
+    pub(crate) password: Option<String>,
+    pub(crate) language: Option<WordlistLanguage>,
+    /// Account number, only used with a master key
+    pub(crate) account: Option<u32>,
+
+    /// Account-level xpub, or its SLIP-132 equivalent
+    pub(crate) xpub: Option<String>,
+    /// Origin of `xpub`, as `[fingerprint/path]`
+    pub(crate) origin: Option<String>,
+}
+
+/// The account-level derivation path of `template`
+pub(crate) fn account_path(template: Template, network: Network, account: u32) -> DerivationPath {
+    let coin_type = match network {
+        Network::Bitcoin => 0,
+        _ => 1,
+    };
+
+    vec![
+        ChildNumber::Hardened {
+            index: template.purpose(),
+        },
+        ChildNumber::Hardened { index: coin_type },
+        ChildNumber::Hardened { index: account },
+    ]
+    .into()
+}
+
+/// The first receive address of `template` for account 0 of `master`, with its derivation path
+pub(crate) fn first_address(
+    template: Template,
+    master: &ExtendedPrivKey,
+) -> Result<(DerivationPath, Address), BdkJniError> {
+    let secp = Secp256k1::new();
+    let path = account_path(template, master.network, 0).extend(&[
+        ChildNumber::Normal { index: 0 },
+        ChildNumber::Normal { index: 0 },
+    ]);
+    let xprv = master.derive_priv(&secp, &path)?;
+    let pubkey = ExtendedPubKey::from_private(&secp, &xprv).public_key;
+
+    let address = match template {
+        Template::Bip44 => Address::p2pkh(&pubkey, master.network),
+        Template::Bip49 => Address::p2shwpkh(&pubkey, master.network)
+            .map_err(|e| BdkJniError::Parsing(e.to_string()))?,
+        Template::Bip84 => Address::p2wpkh(&pubkey, master.network)
+            .map_err(|e| BdkJniError::Parsing(e.to_string()))?,
+    };
+
+    Ok((path, address))
+}
+
+/// Build the descriptor of one keychain, with its checksum and private keys if any
+fn keychain_descriptor<K>(
+    template: Template,
+    key: K,
+    origin: Option<KeySource>,
+    keychain: KeychainKind,
+) -> Result<String, BdkJniError>
+where
+    K: DerivableKey<Legacy> + DerivableKey<Segwitv0>,
+{
+    let path = vec![ChildNumber::Normal {
+        index: keychain as u32,
+    }]
+    .into();
+
+    let (descriptor, keymap, _) = match template {
+        Template::Bip44 => P2Pkh(DerivableKey::<Legacy>::into_descriptor_key(
+            key, origin, path,
+        )?)
+        .build(),
+        Template::Bip49 => P2Wpkh_P2Sh(DerivableKey::<Segwitv0>::into_descriptor_key(
+            key, origin, path,
+        )?)
+        .build(),
+        Template::Bip84 => P2Wpkh(DerivableKey::<Segwitv0>::into_descriptor_key(
+            key, origin, path,
+        )?)
+        .build(),
+    }
+    .map_err(bdk::Error::from)?;
+
+    let (descriptor, _) = descriptor_with_checksum(&descriptor.to_string_with_secret(&keymap))?;
+
+    Ok(descriptor)
+}
+
+/// Build the external and internal descriptors of `key`
+fn keychain_descriptors<K>(
+    template: Template,
+    key: K,
+    origin: Option<KeySource>,
+) -> Result<(String, String), BdkJniError>
+where
+    K: DerivableKey<Legacy> + DerivableKey<Segwitv0> + Copy,
+{
+    Ok((
+        keychain_descriptor(template, key, origin.clone(), KeychainKind::External)?,
+        keychain_descriptor(template, key, origin, KeychainKind::Internal)?,
+    ))
+}
+
+/// The external and internal descriptors of `account` of a master key
+pub(crate) fn master_descriptors(
+    template: Template,
+    master: &ExtendedPrivKey,
+    account: u32,
+) -> Result<(String, String), BdkJniError> {
+    let secp = Secp256k1::new();
+    let path = account_path(template, master.network, account);
+    let account = master.derive_priv(&secp, &path)?;
+
+    keychain_descriptors(template, account, Some((master.fingerprint(&secp), path)))
+}
+
+/// The public external and internal descriptors of `account` of a master key, with the account
+/// xpub and its origin
+pub(crate) fn master_public_descriptors(
+    template: Template,
+    master: &ExtendedPrivKey,
+    account: u32,
+) -> Result<(String, String, ExtendedPubKey, KeySource), BdkJniError> {
+    let secp = Secp256k1::new();
+    let path = account_path(template, master.network, account);
+    let xpub = ExtendedPubKey::from_private(&secp, &master.derive_priv(&secp, &path)?);
+    let origin = (master.fingerprint(&secp), path);
+
+    let (descriptor, change_descriptor) =
+        keychain_descriptors(template, xpub, Some(origin.clone()))?;
+    Ok((descriptor, change_descriptor, xpub, origin))
+}
+
+/// The external and internal descriptors of a template
+///
+/// `network` is only used to restore a mnemonic, the coin type of the derivation path follows
+/// the network of the master key.
+pub(crate) fn descriptors(
+    params: TemplateParams,
+    network: Network,
+) -> Result<(String, String), BdkJniError> {
+    let template = params
+        .template
+        .or_else(|| {
+            let key = params.xpub.as_ref().or_else(|| params.xprv.as_ref())?;
+            slip132::decode(key).ok()?.version.template()
+        })
+        .or_else(|| match params.xpub {
+            None => Some(Template::Bip84),
+            Some(_) => None,
+        })
+        .ok_or_else(|| {
+            BdkJniError::Parsing(
+                "`template` is required unless the key is a master key, ypub or zpub".to_string(),
+            )
+        })?;
+
+    match params.xpub {
+        Some(xpub) => {
+            if params.xprv.is_some() || params.mnemonic.is_some() {
+                return Err(BdkJniError::Parsing(
+                    "Only one of `xprv`, `mnemonic` or `xpub` must be provided".to_string(),
+                ));
+            }
+
+            let xpub = ExtendedPubKey::from_str(&slip132::normalize_key(&xpub))?;
+            let origin = params.origin.as_deref().map(parse_key_origin).transpose()?;
+            keychain_descriptors(template, xpub, origin)
+        }
+        None => {
+            let master = master_xprv(
+                params.xprv,
+                params.mnemonic,
+                params.password,
+                params.language,
+                Some(network),
+            )?;
+            master_descriptors(template, &master, params.account.unwrap_or(0))
+        }
+    }
+}